@@ -1,4 +1,4 @@
-use chess::{fen_reader, perft};
+use chess::{attack_gen, fen_reader, perft};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 /*
@@ -39,5 +39,19 @@ pub fn test_positions(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, test_positions);
+pub fn check_detection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("check detection");
+    let mut game = fen_reader::read_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+    let king = game.white_pieces.kings[0];
+
+    group.bench_function("mailbox attack_gen", |b| {
+        b.iter(|| attack_gen::attack_gen(black_box(&mut game), None).0[king as usize] > 0)
+    });
+
+    group.bench_function("bitboards", |b| {
+        b.iter(|| black_box(&game).bitboards.is_attacked(king, &game.turn.opposite_color()))
+    });
+}
+
+criterion_group!(benches, test_positions, check_detection);
 criterion_main!(benches);
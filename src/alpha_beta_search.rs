@@ -1,104 +1,431 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicI8;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
-use rayon::prelude::IntoParallelIterator;
-use rayon::prelude::ParallelIterator;
 
+use crate::engine::Engine;
 use crate::eval;
 use crate::game;
 use crate::make_move;
 use crate::model;
 use crate::move_gen;
+use crate::move_picker::MovePicker;
 use crate::piece;
+use crate::time_manager::TimeManager;
+use crate::transposition_table::{Entry, Flag};
 use crate::unmake;
 
+/// How many times a quiescence line may search every evasion when the side to move is in check
+/// instead of standing pat. Zero disables the check extension.
+pub const QUIESCENCE_CHECKS: i8 = 2;
+
+/// Width of the zero windows used to test a move against the bound without finding its score.
+const NULL_WINDOW: f64 = 1e-5;
+/// Half width of the first aspiration window in evaluation units, doubled on every fail high or
+/// low until it passes `ASPIRATION_MAX_WINDOW` and the full window is used.
+const ASPIRATION_WINDOW: i32 = 50;
+const ASPIRATION_MAX_WINDOW: i32 = 800;
+/// Shallow iterations are cheap and their scores unstable, they always use the full window.
+const ASPIRATION_MIN_DEPTH: i8 = 3;
+const HELPER_STACK_SIZE: usize = 8 * 1024 * 1024;
+const NULL_MOVE_MIN_DEPTH: i8 = 3;
+const LMR_MIN_DEPTH: i8 = 3;
+/// Moves ordered before this index (the hash and PV moves, the best captures) are never reduced.
+const LMR_MIN_INDEX: usize = 3;
+const REVERSE_FUTILITY_DEPTH: i8 = 3;
+const FUTILITY_DEPTH: i8 = 2;
+/// Margins in evaluation units (a pawn is about 200) per ply of depth left.
+const REVERSE_FUTILITY_MARGIN: i32 = 150;
+const FUTILITY_MARGIN: i32 = 250;
+
+/// Selective search techniques of `alpha_beta_max` and `alpha_beta_min`. Each one can be switched
+/// off through `Engine::options` to measure what it is worth with `suite::test_model_net`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchOptions {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility: bool,
+    pub futility: bool,
+    /// Threads searching with Lazy SMP, only the search without a net uses more than one.
+    pub threads: usize,
+    /// Best lines searched on every iteration, each one without the first moves of the previous
+    /// ones. Only the search without a net looks for more than one.
+    pub multi_pv: usize,
+}
+
+impl SearchOptions {
+    pub fn new() -> SearchOptions {
+        SearchOptions {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            futility: true,
+            threads: 1,
+            multi_pv: 1,
+        }
+    }
+
+    /// Plain principal variation search, without pruning or reductions.
+    pub fn none() -> SearchOptions {
+        SearchOptions {
+            null_move: false,
+            late_move_reductions: false,
+            reverse_futility: false,
+            futility: false,
+            threads: 1,
+            multi_pv: 1,
+        }
+    }
+
+    /// Nothing is pruned or reduced, every move is searched to the full depth.
+    pub fn is_full_width(&self) -> bool {
+        !(self.null_move || self.late_move_reductions || self.reverse_futility || self.futility)
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shared by every thread of a search so another thread can interrupt it. While pondering the
+/// time limit is ignored until `ponderhit` turns the search into a normal one.
+#[derive(Debug, Default)]
+pub struct SearchControl {
+    stop: AtomicBool,
+    ponder: AtomicBool,
+    parent: Option<Arc<SearchControl>>,
+}
+
+impl SearchControl {
+    pub fn new() -> SearchControl {
+        SearchControl::default()
+    }
+
+    pub fn pondering() -> SearchControl {
+        SearchControl {
+            ponder: AtomicBool::new(true),
+            ..SearchControl::default()
+        }
+    }
+
+    /// Control of the helper threads: stopped with `parent` or on its own once the main thread
+    /// is done, and pondering as long as `parent` is.
+    fn helper(parent: &Arc<SearchControl>) -> SearchControl {
+        SearchControl {
+            parent: Some(parent.clone()),
+            ..SearchControl::default()
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) || self.parent.as_ref().is_some_and(|parent| parent.is_stopped())
+    }
+
+    pub fn ponderhit(&self) {
+        self.ponder.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        match &self.parent {
+            Some(parent) => parent.is_pondering(),
+            None => self.ponder.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Once this is true every score still being computed is incomplete, so searches return
+    /// straight away and their callers discard the result instead of storing it.
+    pub fn should_stop(&self, start_time: &Instant, time_limit: Duration) -> bool {
+        self.is_stopped() || (!self.is_pondering() && start_time.elapsed() >= time_limit)
+    }
+}
+
+/// One of the best lines of a MultiPV search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub score: f64,
+    pub pv: Vec<move_gen::Move>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<move_gen::Move>,
+    pub score: f64,
+    pub depth: i8,
+    pub seldepth: i8,
+    pub nodes: u64,
+    pub nps: u64,
+    pub time: Duration,
+    pub pv: Vec<move_gen::Move>,
+    pub tt_hit_rate: f64,
+    pub aspiration_researches: u64,
+    /// `options.multi_pv` lines at most, best first. The first one is `score` and `pv`.
+    pub lines: Vec<Line>,
+}
+
+impl SearchResult {
+    pub fn new() -> SearchResult {
+        SearchResult {
+            best_move: None,
+            score: 0.0,
+            depth: 0,
+            seldepth: 0,
+            nodes: 0,
+            nps: 0,
+            time: Duration::ZERO,
+            pv: Vec::new(),
+            tt_hit_rate: 0.0,
+            aspiration_researches: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    fn update_statistics(&mut self, start_time: &Instant, statistics: &SearchStatistics) {
+        let probes = statistics.tt_probes.load(Ordering::Relaxed);
+
+        self.time = start_time.elapsed();
+        self.nodes = statistics.nodes.load(Ordering::Relaxed);
+        self.nps = (self.nodes as f64 / self.time.as_secs_f64().max(0.001)) as u64;
+        // The root is searched at ply 1.
+        self.seldepth = (statistics.seldepth.load(Ordering::Relaxed) - 1).max(0);
+        self.tt_hit_rate = if probes == 0 {
+            0.0
+        } else {
+            statistics.tt_hits.load(Ordering::Relaxed) as f64 / probes as f64
+        };
+        self.aspiration_researches = statistics.aspiration_researches.load(Ordering::Relaxed);
+    }
+}
+
+impl Default for SearchResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counters of one search, shared through the `Engine` by the main thread and its helpers.
+#[derive(Debug, Default)]
+pub struct SearchStatistics {
+    nodes: AtomicU64,
+    seldepth: AtomicI8,
+    tt_probes: AtomicU64,
+    tt_hits: AtomicU64,
+    aspiration_researches: AtomicU64,
+}
+
+impl SearchStatistics {
+    pub fn new() -> SearchStatistics {
+        SearchStatistics::default()
+    }
+
+    /// Nodes searched so far, also while the search runs.
+    pub fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    fn reset(&self) {
+        self.nodes.store(0, Ordering::Relaxed);
+        self.seldepth.store(0, Ordering::Relaxed);
+        self.tt_probes.store(0, Ordering::Relaxed);
+        self.tt_hits.store(0, Ordering::Relaxed);
+        self.aspiration_researches.store(0, Ordering::Relaxed);
+    }
+
+    fn count_node(&self, ply: i8) {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        self.seldepth.fetch_max(ply, Ordering::Relaxed);
+    }
+}
+
+/// Never cuts the root: an entry left by a previous search of the same position would end the
+/// search without a pv. The root entry still orders the moves through `MovePicker`.
+fn probe_tt(game: &game::Position, engine: &Engine, ply: i8) -> Option<Entry> {
+    if ply == 1 {
+        return None;
+    }
+    let entry = engine.transposition_table.probe(game.hash);
+
+    engine.statistics.tt_probes.fetch_add(1, Ordering::Relaxed);
+    if entry.is_some() {
+        engine.statistics.tt_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    entry.map(|mut entry| {
+        if eval::is_mate_score(entry.value) {
+            entry.value -= entry.value.signum() * ply as f64 * eval::MATE_STEP;
+        }
+        entry
+    })
+}
+
+/// Mate scores are stored as the distance from this node instead of from the root, so they stay
+/// right when the position is reached again at another ply. The root entry belongs to the first
+/// line, the next lines of a MultiPV search do not overwrite it.
+fn store_tt(game: &game::Position, engine: &Engine, value: f64, depth: i8, flag: Flag, best_move: Option<&move_gen::Move>, ply: i8) {
+    if ply == 1 && engine.context.excludes_root_moves() {
+        return;
+    }
+    let value = if eval::is_mate_score(value) {
+        value + value.signum() * ply as f64 * eval::MATE_STEP
+    } else {
+        value
+    };
+
+    engine.transposition_table.store(game.hash, value, depth, flag, best_move);
+}
+
+/// The tables indexed by ply end at `MAX_PLY`, past it the search returns the static evaluation.
+fn is_too_deep(ply: i8) -> bool {
+    ply as usize >= game::MAX_PLY - 1
+}
+
+/// Whether the window is wider than a zero window. `(alpha + NULL_WINDOW) - alpha` is often a bit
+/// more than `NULL_WINDOW` after rounding, so the comparison leaves room for it.
+fn is_pv_node(alpha: f64, beta: f64) -> bool {
+    beta - alpha > 2.0 * NULL_WINDOW
+}
+
+fn null_move_reduction(depth_left: i8) -> i8 {
+    if depth_left >= 6 {
+        3
+    } else {
+        2
+    }
+}
+
+/// Passing is never the best move unless in zugzwang, which is common when the side to move only
+/// has pawns left, so null move pruning is not tried there.
+fn has_non_pawn_material(game: &game::Position) -> bool {
+    let pieces = match game.turn {
+        game::Color::White => &game.white_pieces,
+        game::Color::Black => &game.black_pieces,
+    };
+
+    !(pieces.queens.is_empty() && pieces.rooks.is_empty() && pieces.bishops.is_empty() && pieces.knights.is_empty())
+}
+
+/// Plies to reduce a quiet move the ordering placed late. Killers and root moves are kept at full
+/// depth and moves that never caused a cutoff, with no history score, are reduced one ply more.
+fn late_move_reduction(game: &game::Position, engine: &Engine, movement: &move_gen::Move, index: usize, depth_left: i8, ply: i8, in_check: bool) -> i8 {
+    if ply == 1 || !engine.options.late_move_reductions || in_check || depth_left < LMR_MIN_DEPTH || index < LMR_MIN_INDEX {
+        return 0;
+    }
+    if engine.context.is_killer(movement, ply) {
+        return 0;
+    }
+
+    if depth_left >= 5 && engine.context.history(&game.turn, movement) == 0 {
+        2
+    } else {
+        1
+    }
+}
+
 pub fn alpha_beta_max_net(
     alpha: f64,
     beta: f64,
     depth_left: i8,
-    game: &mut game::GameInfo,
+    game: &mut game::Position,
+    engine: &mut Engine,
     pv: &mut Vec<move_gen::Move>,
     start_time: &Instant,
     time_limit: Duration,
     net: &model::Net,
+    ply: i8,
     ) -> f64 {
+
+    if engine.control.should_stop(start_time, time_limit) {
+        return 0.0;
+    }
+    engine.statistics.count_node(ply);
+    if is_too_deep(ply) {
+        return eval::net_eval(game, net);
+    }
+
+    if ply > 1 && eval::is_draw(game, 2) {
+        return 0.0;
+    }
         
     let mut movements = move_gen::move_gen(game);
     
-    if depth_left == 0 || movements.len() == 0 {
-        return eval::net_eval(game, net);
+    if movements.len() == 0 {
+        return eval::terminal_score(game, ply);
+    }
+    if depth_left == 0 {
+        return quiescence_max(alpha, beta, game, engine, start_time, &time_limit, QUIESCENCE_CHECKS, ply, &|game| eval::net_eval(game, net));
     }
     
     let mut alpha = alpha;
     let mut beta = beta;
     
-    let index: usize = (game.hash % game::TRANSPOSITION_TABLE_SIZE) as usize;
-    let tt = game.transposition_table.lock().unwrap();
-
-    if tt[index].zobrist_key == game.hash && tt[index].depth >= depth_left{
-        match tt[index].flag{
-            game::Flag::Exact => return tt[index].value,
-            game::Flag::Lowerbound => alpha = alpha.max(tt[index].value),
-            game::Flag::Upperbound => beta = beta.min(tt[index].value),
-        }
-        if alpha >= beta {
-            return tt[index].value;
+    if let Some(entry) = probe_tt(game, engine, ply) {
+        if entry.depth >= depth_left {
+            match entry.flag {
+                Flag::Exact => return entry.value,
+                Flag::Lowerbound => alpha = alpha.max(entry.value),
+                Flag::Upperbound => beta = beta.min(entry.value),
+            }
+            if alpha >= beta {
+                return entry.value;
+            }
         }
     }
-    drop(tt);
     
+    // Lower bound of the window the moves are searched in, to tell an exact score from a fail-low.
+    let alpha_orig = alpha;
     let mut new_pv: Vec<move_gen::Move> = Vec::new();
-    let mut first = false;
+    let mut best_move = None;
+    let mut first = true;
     
-    eval::order_moves(&mut movements, &pv, game,1);
+    eval::order_moves(&mut movements, &pv, game, engine, ply);
     
     for mut movement in movements {
     
-        if start_time.elapsed() >= time_limit {
-            return -100.0;
-        }
-    
+        engine.context.set_move(ply, Some(movement));
         make_move::make_move(game, &mut movement);
     
         let mut score;
         if first {
-            score = alpha_beta_min_net(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, net);
+            score = alpha_beta_min_net(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, net, ply + 1);
             first = false;
         } else {
-            score = alpha_beta_min_net(beta - 1.0, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, net);
+            score = alpha_beta_min_net(alpha, alpha + NULL_WINDOW, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, net, ply + 1);
             if score > alpha && score < beta {
-                score = alpha_beta_min_net(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, net);
+                score = alpha_beta_min_net(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, net, ply + 1);
             }
         }
         unmake::unmake_move(game, movement);
+        if engine.control.should_stop(start_time, time_limit) {
+            return 0.0;
+        }
     
         if score >= beta {
+            store_tt(game, engine, score, depth_left, Flag::Lowerbound, Some(&movement), ply);
             return beta;
         }
     
         if score > alpha {
             alpha = score;
+            best_move = Some(movement);
             pv.clear();
             pv.push(movement);
             pv.append(&mut new_pv);
         }
     }
     
-    let flag = if alpha <= alpha {
-        game::Flag::Upperbound
-    } else if alpha >= beta {
-        game::Flag::Lowerbound
-    } else {
-        game::Flag::Exact
-    };
+    let flag = if alpha > alpha_orig { Flag::Exact } else { Flag::Upperbound };
     
-    let mut tt = game.transposition_table.lock().unwrap();
-
-    tt[index].zobrist_key = game.hash;
-    tt[index].flag = flag;
-    tt[index].depth = depth_left;
-    tt[index].value = alpha;
-    drop(tt);
+    store_tt(game, engine, alpha, depth_left, flag, best_move.as_ref(), ply);
 
     alpha
 }
@@ -107,414 +434,730 @@ pub fn alpha_beta_min_net(
     alpha: f64,
     beta: f64,
     depth_left: i8,
-    game: &mut game::GameInfo,
+    game: &mut game::Position,
+    engine: &mut Engine,
     pv: &mut Vec<move_gen::Move>,
     start_time: &Instant,
     time_limit: Duration,
     net: &model::Net,
+    ply: i8,
     ) -> f64 {
         
-    if start_time.elapsed() >= time_limit {
-        return 100.0;
+    if engine.control.should_stop(start_time, time_limit) {
+        return 0.0;
+    }
+    engine.statistics.count_node(ply);
+    if is_too_deep(ply) {
+        return eval::net_eval(game, net);
+    }
+
+    if ply > 1 && eval::is_draw(game, 2) {
+        return 0.0;
     }
 
     let mut movements = move_gen::move_gen(game);
     
-    if depth_left == 0 || movements.len() == 0 {
-        return eval::net_eval(game, net);
+    if movements.len() == 0 {
+        return eval::terminal_score(game, ply);
+    }
+    if depth_left == 0 {
+        return quiescence_min(alpha, beta, game, engine, start_time, &time_limit, QUIESCENCE_CHECKS, ply, &|game| eval::net_eval(game, net));
     }
     
     let mut alpha = alpha;
     let mut beta = beta;
     
-    let index = (game.hash % game::TRANSPOSITION_TABLE_SIZE) as usize;
-    let tt = game.transposition_table.lock().unwrap();
-    if tt[index].zobrist_key == game.hash && tt[index].depth >= depth_left{
-        match tt[index].flag{
-            game::Flag::Exact => return tt[index].value,
-            game::Flag::Lowerbound => alpha = alpha.max(tt[index].value),
-            game::Flag::Upperbound => beta = beta.min(tt[index].value),
-        }
-        if alpha >= beta {
-            return tt[index].value;
+    if let Some(entry) = probe_tt(game, engine, ply) {
+        if entry.depth >= depth_left {
+            match entry.flag {
+                Flag::Exact => return entry.value,
+                Flag::Lowerbound => alpha = alpha.max(entry.value),
+                Flag::Upperbound => beta = beta.min(entry.value),
+            }
+            if alpha >= beta {
+                return entry.value;
+            }
         }
     }
-    drop(tt);
     
+    // Upper bound of the window the moves are searched in, to tell an exact score from a fail-high.
+    let beta_orig = beta;
     let mut new_pv: Vec<move_gen::Move> = Vec::new();
-    let mut first = false;
+    let mut best_move = None;
+    let mut first = true;
     
-    eval::order_moves(&mut movements, &pv, game, 1);
+    eval::order_moves(&mut movements, &pv, game, engine, ply);
     
     for mut movement in movements {
     
+        engine.context.set_move(ply, Some(movement));
         make_move::make_move(game, &mut movement);
     
         let mut score;
         if first {
-            score = alpha_beta_max_net(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, net);
+            score = alpha_beta_max_net(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, net, ply + 1);
             first = false;
         } else {
-            score = alpha_beta_max_net(alpha, beta - 1.0, depth_left - 1, game, &mut new_pv, start_time, time_limit, net);
+            score = alpha_beta_max_net(beta - NULL_WINDOW, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, net, ply + 1);
             if score < beta && score > alpha {
-                score = alpha_beta_max_net(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, net);
+                score = alpha_beta_max_net(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, net, ply + 1);
             }
         }
         unmake::unmake_move(game, movement);
+        if engine.control.should_stop(start_time, time_limit) {
+            return 0.0;
+        }
     
         if score <= alpha {
+            store_tt(game, engine, score, depth_left, Flag::Upperbound, Some(&movement), ply);
             return alpha;
         }
     
         if score < beta {
             beta = score;
+            best_move = Some(movement);
             pv.clear();
             pv.push(movement);
             pv.append(&mut new_pv);
         }
     }
     
-    let flag = if beta <= alpha {
-        game::Flag::Lowerbound
-    } else if beta >= beta {
-        game::Flag::Upperbound
-    } else {
-        game::Flag::Exact
-    };
+    let flag = if beta < beta_orig { Flag::Exact } else { Flag::Lowerbound };
     
-    let mut tt = game.transposition_table.lock().unwrap();
-
-    tt[index].zobrist_key = game.hash;
-    tt[index].flag = flag;
-    tt[index].depth = depth_left;
-    tt[index].value = beta;
-    drop(tt);
+    store_tt(game, engine, beta, depth_left, flag, best_move.as_ref(), ply);
 
     beta
     
 }
    
 
-pub fn alpha_beta_max(alpha: f64, beta: f64, depth_left: i8, game: &mut game::GameInfo, pv: &mut Vec<move_gen::Move>,start_time: &Instant, time_limit: &Duration, ply: i8, max_depth: i8) -> f64 {
+pub fn alpha_beta_max(alpha: f64, beta: f64, depth_left: i8, game: &mut game::Position, engine: &mut Engine, pv: &mut Vec<move_gen::Move>,start_time: &Instant, time_limit: &Duration, ply: i8, max_depth: i8) -> f64 {
 
-    if start_time.elapsed() >= *time_limit {
-        return -100.0;
+    if engine.control.should_stop(start_time, *time_limit) {
+        return 0.0;
     }
-
-    let mut movements = move_gen::move_gen(game);
-    
-    if depth_left == 0 || movements.len() == 0 {
+    engine.statistics.count_node(ply);
+    if is_too_deep(ply) {
         return eval::static_evaluate(game);
     }
 
+    if ply > 1 && eval::is_draw(game, 2) {
+        return 0.0;
+    }
+
+    if depth_left == 0 {
+        return quiescence_max(alpha, beta, game, engine, start_time, time_limit, QUIESCENCE_CHECKS, ply, &eval::static_evaluate);
+    }
+
+    let pv_node = is_pv_node(alpha, beta);
     let mut alpha = alpha;
     let mut beta = beta;
     
-    let index = (game.hash % game::TRANSPOSITION_TABLE_SIZE) as usize;
-    let tt = game.transposition_table.lock().unwrap();
-    if tt[index].zobrist_key == game.hash && tt[index].depth >= depth_left{
-        match tt[index].flag{
-            game::Flag::Exact => return tt[index].value,
-            game::Flag::Lowerbound => alpha = alpha.max(tt[index].value),
-            game::Flag::Upperbound => beta = beta.min(tt[index].value),
-        }
-        if alpha >= beta {
-            return tt[index].value;
+    if let Some(entry) = probe_tt(game, engine, ply) {
+        if entry.depth >= depth_left {
+            match entry.flag {
+                Flag::Exact => return entry.value,
+                Flag::Lowerbound => alpha = alpha.max(entry.value),
+                Flag::Upperbound => beta = beta.min(entry.value),
+            }
+            if alpha >= beta {
+                return entry.value;
+            }
         }
     }
-    drop(tt);
 
-    let mut first = false;
+    let options = engine.options;
+    let in_check = game.in_check();
+    let prune = !pv_node && !in_check && ply > 1;
+    // Only looked at when `prune` holds.
+    let static_eval = if prune { eval::static_evaluate(game) } else { 0.0 };
 
-    eval::order_moves(&mut movements, &pv, game, ply);
-    if depth_left > 1{
-        
-        let result: Vec<_> = movements
-            .into_par_iter()
-            .map(|mut movement| {
-                let mut game_clone = game.clone();
-                let mut new_pv: Vec<move_gen::Move> = Vec::new();
+    if prune
+        && options.reverse_futility
+        && depth_left <= REVERSE_FUTILITY_DEPTH
+        && !eval::is_mate_score(beta)
+        && eval::add_margin(static_eval, -REVERSE_FUTILITY_MARGIN * depth_left as i32) >= beta
+    {
+        return beta;
+    }
 
-                make_move::make_move(&mut game_clone, &mut movement);
+    if prune
+        && options.null_move
+        && depth_left >= NULL_MOVE_MIN_DEPTH
+        && static_eval >= beta
+        && !eval::is_mate_score(beta)
+        && has_non_pawn_material(game)
+    {
+        engine.context.set_move(ply, None);
+        make_move::make_null_move(game);
+        let score = alpha_beta_min(beta - NULL_WINDOW, beta, depth_left - 1 - null_move_reduction(depth_left), game, engine, &mut Vec::new(), start_time, time_limit, ply + 1, max_depth);
+        unmake::unmake_null_move(game);
+        if engine.control.should_stop(start_time, *time_limit) {
+            return 0.0;
+        }
 
-                let mut score = alpha_beta_min(beta - 1.0, beta, depth_left - 1, &mut game_clone, &mut new_pv, start_time, time_limit, ply+1, max_depth);
-                if score > alpha && score < beta {
-                    score = alpha_beta_min(alpha, beta, depth_left - 1, &mut game_clone, &mut new_pv, start_time, time_limit, ply+1, max_depth);
-                }
+        if score >= beta {
+            return beta;
+        }
+    }
 
-                unmake::unmake_move(&mut game_clone, movement);
-
-                (score, movement, new_pv)
-            })
-            .collect();
-
-        for (score, movement, mut new_pv) in result {
-            if score >= beta {
-                if movement.destiny_piece == piece::Piece::Empty {
-                    let side = if game.turn == game::Color::White { 0 } else { 1 };
-                    game.historic_heuristic.lock().unwrap()[side][movement.origin as usize][movement.destiny as usize] += (depth_left) as usize;
-                    let mut killer_move = game.killer_move.lock().unwrap();
-                    if killer_move[ply as usize][0] != movement {
-                        killer_move[ply as usize][1] = killer_move[ply as usize][0];
-                        killer_move[ply as usize][0] = movement;
-                    }
-                }
+    let futile = prune
+        && options.futility
+        && depth_left <= FUTILITY_DEPTH
+        && eval::add_margin(static_eval, FUTILITY_MARGIN * depth_left as i32) <= alpha;
 
-                game.store_tt(index, game::Flag::Lowerbound, score, depth_left);
+    let mut picker = MovePicker::new(if ply == 1 { pv.first().copied() } else { None }, ply, in_check);
+    let mut index = 0;
 
-                return beta;
-            }
+    while let Some(mut movement) = picker.next(game, engine) {
+        index += 1;
+        let index = index - 1;
+        let mut new_pv: Vec<move_gen::Move> = Vec::new();
+        let quiet = index > 0 && !eval::is_capture(&movement);
+        let mut reduction = if quiet { late_move_reduction(game, engine, &movement, index, depth_left, ply, in_check) } else { 0 };
+
+        engine.context.set_move(ply, Some(movement));
+        make_move::make_move(game, &mut movement);
 
-            if score > alpha {
-                alpha = score;
-                pv.clear();
-                pv.push(movement);
-                pv.append(&mut new_pv);
-                game.store_tt(index, game::Flag::Exact, alpha, depth_left);
+        let gives_check = quiet && (futile || reduction > 0) && game.in_check();
+        if quiet && futile && !gives_check {
+            unmake::unmake_move(game, movement);
+            continue;
+        }
+        if gives_check {
+            reduction = 0;
+        }
+
+        let mut score;
+        if index == 0 {
+            score = alpha_beta_min(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
+        } else {
+            score = alpha_beta_min(alpha, alpha + NULL_WINDOW, depth_left - 1 - reduction, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
+            if reduction > 0 && score > alpha {
+                score = alpha_beta_min(alpha, alpha + NULL_WINDOW, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
+            }
+            if score > alpha && score < beta {
+                score = alpha_beta_min(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
             }
         }
 
-    }else{
-        for mut movement in movements {
-            let mut new_pv: Vec<move_gen::Move> = Vec::new();
-            make_move::make_move(game, &mut movement);
+        unmake::unmake_move(game, movement);
+        if engine.control.should_stop(start_time, *time_limit) {
+            return 0.0;
+        }
 
-            let mut score;
-            if first{
-                score = alpha_beta_min(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, ply+1, max_depth);
-                first = false;
-            }else{
-                score = alpha_beta_min(beta - 1.0, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, ply+1, max_depth);
-                if score > alpha && score < beta {
-                    score = alpha_beta_min(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, ply+1, max_depth);
-                }
+        if score >= beta {
+            if movement.destiny_piece == piece::Piece::Empty {
+                engine.context.update_cutoff(&game.turn, movement, ply, depth_left);
             }
-            unmake::unmake_move(game, movement);
+            store_tt(game, engine, score, depth_left, Flag::Lowerbound, Some(&movement), ply);
 
-            if score >= beta {
-                if movement.destiny_piece == piece::Piece::Empty{
+            return beta;
+        }
 
-                    let side = if game.turn == game::Color::White {0} else {1};
-                    game.historic_heuristic.lock().unwrap()[side][movement.origin as usize][movement.destiny as usize] +=( depth_left) as usize;
-                    let mut killer_move = game.killer_move.lock().unwrap();
-                    if killer_move[ply as usize][0] != movement{
-                        killer_move[ply as usize][1] = killer_move[ply as usize][0];
-                        killer_move[ply as usize][0] = movement;
-                    }
-                }
-                game.store_tt(index, game::Flag::Lowerbound, score, depth_left);
+        if score > alpha {
+            alpha = score;
+            pv.clear();
+            pv.push(movement);
+            pv.append(&mut new_pv);
+            store_tt(game, engine, alpha, depth_left, Flag::Exact, Some(&movement), ply);
+        }
+    }
 
-                return beta;
-            }
+    // No legal move: mate or stalemate.
+    if index == 0 {
+        return eval::terminal_score(game, ply);
+    }
 
-            if score > alpha {
-                alpha = score; 
-                pv.clear();
-                pv.push(movement);
-                pv.append(&mut new_pv);
-                game.store_tt(index, game::Flag::Exact, alpha, depth_left);
-            }
-        }
-    }   
-        
     alpha
 }
 
-pub fn alpha_beta_min(alpha: f64, beta: f64, depth_left: i8, game: &mut game::GameInfo, pv: &mut Vec<move_gen::Move>, start_time: &Instant, time_limit: &Duration, ply: i8, max_depth: i8) -> f64 {
+pub fn alpha_beta_min(alpha: f64, beta: f64, depth_left: i8, game: &mut game::Position, engine: &mut Engine, pv: &mut Vec<move_gen::Move>, start_time: &Instant, time_limit: &Duration, ply: i8, max_depth: i8) -> f64 {
     
-    if start_time.elapsed() >= *time_limit {
-        return 100.0;
+    if engine.control.should_stop(start_time, *time_limit) {
+        return 0.0;
+    }
+    engine.statistics.count_node(ply);
+    if is_too_deep(ply) {
+        return eval::static_evaluate(game);
     }
 
-    let mut movements = move_gen::move_gen(game);
+    if ply > 1 && eval::is_draw(game, 2) {
+        return 0.0;
+    }
 
-    if depth_left == 0 || movements.len() == 0{
-        return eval::static_evaluate(game);
+    if depth_left == 0 {
+        return quiescence_min(alpha, beta, game, engine, start_time, time_limit, QUIESCENCE_CHECKS, ply, &eval::static_evaluate);
     }
 
+    let pv_node = is_pv_node(alpha, beta);
     let mut beta = beta;
     let mut alpha = alpha;
     
-    let index = (game.hash % game::TRANSPOSITION_TABLE_SIZE) as usize;
-    let tt = game.transposition_table.lock().unwrap();
-    if tt[index].zobrist_key == game.hash && tt[index].depth >= depth_left{
-        match tt[index].flag{
-            game::Flag::Exact => return tt[index].value,
-            game::Flag::Lowerbound => alpha = alpha.max(tt[index].value),
-            game::Flag::Upperbound => beta = beta.min(tt[index].value),
-        }
-        if alpha >= beta {
-            return tt[index].value;
+    if let Some(entry) = probe_tt(game, engine, ply) {
+        if entry.depth >= depth_left {
+            match entry.flag {
+                Flag::Exact => return entry.value,
+                Flag::Lowerbound => alpha = alpha.max(entry.value),
+                Flag::Upperbound => beta = beta.min(entry.value),
+            }
+            if alpha >= beta {
+                return entry.value;
+            }
         }
     }
-    drop(tt);
     
-    eval::order_moves(&mut movements, &pv, game, ply);
-    let mut first = true;
+    let options = engine.options;
+    let in_check = game.in_check();
+    let prune = !pv_node && !in_check && ply > 1;
+    // Only looked at when `prune` holds.
+    let static_eval = if prune { eval::static_evaluate(game) } else { 0.0 };
 
-    if depth_left > 1{
-        
-        let result: Vec<_> = movements
-        .into_par_iter()
-        .map(|mut movement| {
-            let mut game_clone = game.clone();
-            let mut new_pv: Vec<move_gen::Move> = Vec::new();
-
-            make_move::make_move(&mut game_clone, &mut movement);
-            
-            let mut score = alpha_beta_max(alpha, alpha + 1.0, depth_left - 1, &mut game_clone, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
-            if score > alpha && score < beta {
-                score = alpha_beta_max(alpha, beta, depth_left - 1, &mut game_clone, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
-            }
+    if prune
+        && options.reverse_futility
+        && depth_left <= REVERSE_FUTILITY_DEPTH
+        && !eval::is_mate_score(alpha)
+        && eval::add_margin(static_eval, REVERSE_FUTILITY_MARGIN * depth_left as i32) <= alpha
+    {
+        return alpha;
+    }
 
-            unmake::unmake_move(&mut game_clone, movement);
+    if prune
+        && options.null_move
+        && depth_left >= NULL_MOVE_MIN_DEPTH
+        && static_eval <= alpha
+        && !eval::is_mate_score(alpha)
+        && has_non_pawn_material(game)
+    {
+        engine.context.set_move(ply, None);
+        make_move::make_null_move(game);
+        let score = alpha_beta_max(alpha, alpha + NULL_WINDOW, depth_left - 1 - null_move_reduction(depth_left), game, engine, &mut Vec::new(), start_time, time_limit, ply + 1, max_depth);
+        unmake::unmake_null_move(game);
+        if engine.control.should_stop(start_time, *time_limit) {
+            return 0.0;
+        }
 
-            (score, movement, new_pv)
-        })
-        .collect();
+        if score <= alpha {
+            return alpha;
+        }
+    }
 
-        for (score, movement, mut new_pv) in result {
-            if score <= alpha {
-                if movement.destiny_piece == piece::Piece::Empty {
-                    let side = if game.turn == game::Color::White { 0 } else { 1 };
-                    game.historic_heuristic.lock().unwrap()[side][movement.origin as usize][movement.destiny as usize] += (depth_left) as usize;
-                    let mut killer_move = game.killer_move.lock().unwrap();
+    let futile = prune
+        && options.futility
+        && depth_left <= FUTILITY_DEPTH
+        && eval::add_margin(static_eval, -FUTILITY_MARGIN * depth_left as i32) >= beta;
 
-                    if killer_move[ply as usize][0] != movement {
-                        killer_move[ply as usize][1] = killer_move[ply as usize][0];
-                        killer_move[ply as usize][0] = movement;
-                    }
-                }
+    let mut picker = MovePicker::new(if ply == 1 { pv.first().copied() } else { None }, ply, in_check);
+    let mut index = 0;
 
-                game.store_tt(index, game::Flag::Upperbound, score, depth_left);
-                
-                return alpha;
-            }
+    while let Some(mut movement) = picker.next(game, engine) {
+        index += 1;
+        let index = index - 1;
+        let mut new_pv: Vec<move_gen::Move> = Vec::new();
+        let quiet = index > 0 && !eval::is_capture(&movement);
+        let mut reduction = if quiet { late_move_reduction(game, engine, &movement, index, depth_left, ply, in_check) } else { 0 };
 
-            if score < beta {
-                beta = score;
-                pv.clear();
-                pv.push(movement);
-                pv.append(&mut new_pv);
-                game.store_tt(index, game::Flag::Exact, beta, depth_left);
-            }
-        }
-    }else{
-
-        for mut movement in movements {
-            let mut new_pv: Vec<move_gen::Move> = Vec::new();
-            make_move::make_move(game, &mut movement);
-            let mut score;
-            if first{
-                score = alpha_beta_max(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, ply+1, max_depth);
-                first = false;
-            } else {
-                score = alpha_beta_max(alpha, alpha + 1.0, depth_left - 1, game, &mut new_pv,start_time, time_limit, ply+1, max_depth);
-                if score > alpha && score < beta {
-                    score = alpha_beta_max(alpha, beta, depth_left - 1, game, &mut new_pv, start_time, time_limit, ply+1, max_depth);
-                }
-            }
-            
+        engine.context.set_move(ply, Some(movement));
+        make_move::make_move(game, &mut movement);
+
+        let gives_check = quiet && (futile || reduction > 0) && game.in_check();
+        if quiet && futile && !gives_check {
             unmake::unmake_move(game, movement);
-    
-            if score <= alpha {
-                if movement.destiny_piece == piece::Piece::Empty{
-    
-                    let side = if game.turn == game::Color::White {0} else {1};
-                    game.historic_heuristic.lock().unwrap()[side][movement.origin as usize][movement.destiny as usize] +=( depth_left) as usize;
-                    let mut killer_move = game.killer_move.lock().unwrap();
-    
-                    if killer_move[ply as usize][0] != movement{
-                        killer_move[ply as usize][1] = killer_move[ply as usize][0];
-                        killer_move[ply as usize][0] = movement;
-                    }
-                }
+            continue;
+        }
+        if gives_check {
+            reduction = 0;
+        }
 
-                game.store_tt(index, game::Flag::Upperbound, score, depth_left);
-    
-                return alpha; 
+        let mut score;
+        if index == 0 {
+            score = alpha_beta_max(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
+        } else {
+            score = alpha_beta_max(beta - NULL_WINDOW, beta, depth_left - 1 - reduction, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
+            if reduction > 0 && score < beta {
+                score = alpha_beta_max(beta - NULL_WINDOW, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
             }
-    
-            if score < beta {
-                beta = score;
-                pv.clear();
-                pv.push(movement);
-                pv.append(&mut new_pv);
-                game.store_tt(index, game::Flag::Exact, beta, depth_left)
+            if score > alpha && score < beta {
+                score = alpha_beta_max(alpha, beta, depth_left - 1, game, engine, &mut new_pv, start_time, time_limit, ply + 1, max_depth);
             }
         }
+
+        unmake::unmake_move(game, movement);
+        if engine.control.should_stop(start_time, *time_limit) {
+            return 0.0;
+        }
+
+        if score <= alpha {
+            if movement.destiny_piece == piece::Piece::Empty {
+                engine.context.update_cutoff(&game.turn, movement, ply, depth_left);
+            }
+
+            store_tt(game, engine, score, depth_left, Flag::Upperbound, Some(&movement), ply);
+
+            return alpha;
+        }
+
+        if score < beta {
+            beta = score;
+            pv.clear();
+            pv.push(movement);
+            pv.append(&mut new_pv);
+            store_tt(game, engine, beta, depth_left, Flag::Exact, Some(&movement), ply);
+        }
+    }
+
+    // No legal move: mate or stalemate.
+    if index == 0 {
+        return eval::terminal_score(game, ply);
     }
 
- 
     beta
 }
 
 
-pub fn iterative_deepening_time_limit(game: &mut game::GameInfo, max_depth: i8, time_limit: Duration) -> (Option<(move_gen::Move)>,f64) {
-    let mut best_move: Option<move_gen::Move> = None;
-    let mut pv: Vec<move_gen::Move> = Vec::new();
-    let mut score = 0.0;
-    let start_time = Instant::now();
+/// Capture and promotion only search used at the horizon of the main search, so the static
+/// evaluation is only trusted in quiet positions.
+pub fn quiescence_max(
+    alpha: f64,
+    beta: f64,
+    game: &mut game::Position,
+    engine: &mut Engine,
+    start_time: &Instant,
+    time_limit: &Duration,
+    checks_left: i8,
+    ply: i8,
+    evaluate: &dyn Fn(&mut game::Position) -> f64,
+) -> f64 {
+    if engine.control.should_stop(start_time, *time_limit) {
+        return 0.0;
+    }
+    engine.statistics.count_node(ply);
+    if is_too_deep(ply) {
+        return evaluate(game);
+    }
 
-    for depth in 1..=max_depth {
-        let alpha = -100.0;
-        let beta = 100.0;
+    let in_check = game.in_check();
+    let mut movements = if in_check { move_gen::evasions(game) } else { move_gen::captures(game) };
 
-        if game.turn == game::Color::White {
-            score = alpha_beta_max(alpha, beta, depth, game, &mut pv, &start_time, &time_limit, 1, depth);
-        } else {
-            score = alpha_beta_min(alpha, beta, depth, game, &mut pv, &start_time, &time_limit, 1, depth);
+    if in_check && movements.is_empty() {
+        return eval::terminal_score(game, ply);
+    }
+
+    let mut alpha = alpha;
+    let extend = in_check && checks_left > 0;
+
+    if extend {
+        eval::order_moves(&mut movements, &Vec::new(), game, engine, ply);
+    } else {
+        let stand_pat = evaluate(game);
+
+        if stand_pat >= beta {
+            return beta;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
         }
 
-        if pv.len() > 0 {
-            best_move = Some(pv[0]);
+        // Captures that lose material by SEE are not worth searching.
+        movements.retain(|movement| eval::is_capture(movement) && eval::see(game, movement) >= 0);
+        eval::order_captures(&mut movements, game);
+    }
+
+    for mut movement in movements {
+        engine.context.set_move(ply, Some(movement));
+        make_move::make_move(game, &mut movement);
+        let score = quiescence_min(alpha, beta, game, engine, start_time, time_limit, checks_left - extend as i8, ply + 1, evaluate);
+        unmake::unmake_move(game, movement);
+
+        if score >= beta {
+            return beta;
         }
-        if score == -1.0 || score == 1.0 {
-            break;
+        if score > alpha {
+            alpha = score;
         }
+    }
 
-        if start_time.elapsed() >= time_limit {
-            break;
+    alpha
+}
+
+pub fn quiescence_min(
+    alpha: f64,
+    beta: f64,
+    game: &mut game::Position,
+    engine: &mut Engine,
+    start_time: &Instant,
+    time_limit: &Duration,
+    checks_left: i8,
+    ply: i8,
+    evaluate: &dyn Fn(&mut game::Position) -> f64,
+) -> f64 {
+    if engine.control.should_stop(start_time, *time_limit) {
+        return 0.0;
+    }
+    engine.statistics.count_node(ply);
+    if is_too_deep(ply) {
+        return evaluate(game);
+    }
+
+    let in_check = game.in_check();
+    let mut movements = if in_check { move_gen::evasions(game) } else { move_gen::captures(game) };
+
+    if in_check && movements.is_empty() {
+        return eval::terminal_score(game, ply);
+    }
+
+    let mut beta = beta;
+    let extend = in_check && checks_left > 0;
+
+    if extend {
+        eval::order_moves(&mut movements, &Vec::new(), game, engine, ply);
+    } else {
+        let stand_pat = evaluate(game);
+
+        if stand_pat <= alpha {
+            return alpha;
+        }
+        if stand_pat < beta {
+            beta = stand_pat;
         }
-        //println!("{},{}",depth,start_time.elapsed().as_millis());
+
+        // Captures that lose material by SEE are not worth searching.
+        movements.retain(|movement| eval::is_capture(movement) && eval::see(game, movement) >= 0);
+        eval::order_captures(&mut movements, game);
     }
 
-    (best_move,score)
+    for mut movement in movements {
+        engine.context.set_move(ply, Some(movement));
+        make_move::make_move(game, &mut movement);
+        let score = quiescence_max(alpha, beta, game, engine, start_time, time_limit, checks_left - extend as i8, ply + 1, evaluate);
+        unmake::unmake_move(game, movement);
+
+        if score <= alpha {
+            return alpha;
+        }
+        if score < beta {
+            beta = score;
+        }
+    }
+
+    beta
+}
+
+/// A mate found by an iteration that searched at least as deep. It is the shortest one only when
+/// that search was full width, pruning and reductions can hide a shorter mate.
+fn is_mate_within(score: f64, depth: i8) -> bool {
+    eval::is_mate_score(score) && eval::mate_distance(score) <= depth
+}
+
+pub fn iterative_deepening_time_limit(game: &mut game::Position, engine: &mut Engine, max_depth: i8, time_limit: Duration) -> SearchResult {
+    iterative_deepening_time_limit_info(game, engine, max_depth, time_limit, &mut |_| ())
+}
+
+/// Same as `iterative_deepening_time_limit`, calling `on_iteration` with the result of every
+/// iteration that finished inside the time limit.
+pub fn iterative_deepening_time_limit_info(
+    game: &mut game::Position,
+    engine: &mut Engine,
+    max_depth: i8,
+    time_limit: Duration,
+    on_iteration: &mut dyn FnMut(&SearchResult),
+) -> SearchResult {
+    iterative_deepening(game, engine, max_depth, &mut TimeManager::fixed(time_limit), None, on_iteration)
+}
+
+/// Searches for as long as `time_manager` allows, for games played on a clock. Searching every
+/// move of a game with the same `engine` lets the history of the previous searches order the next.
+pub fn iterative_deepening_time_manager(
+    game: &mut game::Position,
+    engine: &mut Engine,
+    max_depth: i8,
+    time_manager: &mut TimeManager,
+    on_iteration: &mut dyn FnMut(&SearchResult),
+) -> SearchResult {
+    iterative_deepening(game, engine, max_depth, time_manager, None, on_iteration)
 }
 
 pub fn iterative_deepening_time_limit_net(
-    game: &mut game::GameInfo,
+    game: &mut game::Position,
+    engine: &mut Engine,
     max_depth: i8,
     time_limit: Duration,
     net: &model::Net,
-    ) -> Option<move_gen::Move> {
-    let mut best_move: Option<move_gen::Move> = None;
-    let start_time = Instant::now();
-    let mut pv: Vec<move_gen::Move> = Vec::new();
-        
-    for depth in 1..=max_depth {
-        let alpha = -100.0;
-        let beta = 100.0;
-        let score;
-    
-        if game.turn == game::Color::White {
-            score = alpha_beta_max_net(alpha, beta, depth, game, &mut pv, &start_time, time_limit, net);
-        } else {
-            score = alpha_beta_min_net(alpha, beta, depth, game, &mut pv, &start_time, time_limit, net);
+    ) -> SearchResult {
+    iterative_deepening_time_limit_net_info(game, engine, max_depth, time_limit, net, &mut |_| ())
+}
+
+pub fn iterative_deepening_time_limit_net_info(
+    game: &mut game::Position,
+    engine: &mut Engine,
+    max_depth: i8,
+    time_limit: Duration,
+    net: &model::Net,
+    on_iteration: &mut dyn FnMut(&SearchResult),
+    ) -> SearchResult {
+    iterative_deepening(game, engine, max_depth, &mut TimeManager::fixed(time_limit), Some(net), on_iteration)
+}
+
+/// Lazy SMP: helper threads run the same iterative deepening on their own copy of the position,
+/// with their own `SearchContext`, and only share the transposition table with the main
+/// thread. Their entries make the main search faster, the move played is the main thread's.
+fn iterative_deepening(
+    game: &mut game::Position,
+    engine: &mut Engine,
+    max_depth: i8,
+    time_manager: &mut TimeManager,
+    net: Option<&model::Net>,
+    on_iteration: &mut dyn FnMut(&SearchResult),
+) -> SearchResult {
+    // The net cannot be shared between threads.
+    let threads = if net.is_some() { 1 } else { engine.options.threads.max(1) };
+    let helpers_control = Arc::new(SearchControl::helper(&engine.control));
+    engine.statistics.reset();
+    engine.transposition_table.new_search();
+    engine.context.new_search();
+
+    thread::scope(|scope| {
+        for id in 1..threads {
+            let mut helper = game.clone();
+            let mut helper_engine = engine.helper(helpers_control.clone());
+            let time_limit = time_manager.hard_limit();
+
+            thread::Builder::new()
+                .stack_size(HELPER_STACK_SIZE)
+                .spawn_scoped(scope, move || {
+                    // Half of the helpers skip the first iteration so they are not all on the
+                    // same depth.
+                    let start_depth = 1 + (id % 2) as i8;
+                    search_iterations(&mut helper, &mut helper_engine, start_depth, max_depth, &mut TimeManager::fixed(time_limit), None, &mut |_| ());
+                })
+                .unwrap();
         }
-    
-        if pv.len() > 0 {
-            best_move = Some(pv[0]);
+
+        let result = search_iterations(game, engine, 1, max_depth, time_manager, net, on_iteration);
+        helpers_control.stop();
+        result
+    })
+}
+
+/// Deepens until `max_depth`, a proven mate or the time manager stops it. Only one iteration is
+/// searched when there is a single legal move.
+fn search_iterations(
+    game: &mut game::Position,
+    engine: &mut Engine,
+    start_depth: i8,
+    max_depth: i8,
+    time_manager: &mut TimeManager,
+    net: Option<&model::Net>,
+    on_iteration: &mut dyn FnMut(&SearchResult),
+) -> SearchResult {
+    let mut result = SearchResult::new();
+    let start_time = Instant::now();
+    let time_limit = time_manager.hard_limit();
+    let legal_moves = move_gen::move_gen(game);
+    let control = engine.control.clone();
+    let statistics = engine.statistics.clone();
+    let lines = if net.is_some() { 1 } else { engine.options.multi_pv.clamp(1, legal_moves.len().max(1)) };
+    // The mate of the previous iteration, the selective search stops once the next one confirms it.
+    let mut mate: Option<f64> = None;
+
+    'deepening: for depth in start_depth..=max_depth {
+        let mut found: Vec<Line> = Vec::new();
+
+        for line in 0..lines {
+            let previous = result.lines.get(line);
+            let previous_score = previous.map_or(0.0, |line| line.score);
+            let mut pv = previous.map(|line| line.pv.clone()).unwrap_or_default();
+            engine.context.exclude_root_moves(found.iter().filter_map(|line| line.pv.first().copied()).collect());
+
+            let score = aspiration_search(previous_score, depth, &control, &statistics, &start_time, time_limit, &mut |alpha, beta| {
+                match (net, game.turn) {
+                    (Some(net), game::Color::White) => alpha_beta_max_net(alpha, beta, depth, game, engine, &mut pv, &start_time, time_limit, net, 1),
+                    (Some(net), game::Color::Black) => alpha_beta_min_net(alpha, beta, depth, game, engine, &mut pv, &start_time, time_limit, net, 1),
+                    (None, game::Color::White) => alpha_beta_max(alpha, beta, depth, game, engine, &mut pv, &start_time, &time_limit, 1, depth),
+                    (None, game::Color::Black) => alpha_beta_min(alpha, beta, depth, game, engine, &mut pv, &start_time, &time_limit, 1, depth),
+                }
+            });
+            engine.context.exclude_root_moves(Vec::new());
+
+            // An interrupted iteration is thrown away, its scores and pv are incomplete.
+            match score {
+                Some(score) => found.push(Line { score, pv }),
+                None => break 'deepening,
+            }
         }
-        if score == -1.0 || score == 1.0 {
+
+        // Every line is searched without the better ones, but a later line can still come out
+        // better when the earlier search was inexact.
+        found.sort_by(|a, b| match game.turn {
+            game::Color::White => b.score.total_cmp(&a.score),
+            game::Color::Black => a.score.total_cmp(&b.score),
+        });
+        result.score = found[0].score;
+        result.depth = depth;
+        result.best_move = found[0].pv.first().copied();
+        result.pv = found[0].pv.clone();
+        result.lines = found;
+        result.update_statistics(&start_time, &statistics);
+        on_iteration(&result);
+
+        if legal_moves.len() == 1 {
             break;
         }
-    
-        if start_time.elapsed() >= time_limit {
+        if is_mate_within(result.score, depth) {
+            if engine.options.is_full_width() || mate == Some(result.score) {
+                break;
+            }
+            mate = Some(result.score);
+        } else {
+            mate = None;
+        }
+        // Pondering goes on until `ponderhit` or `stop`, the time manager only decides afterwards.
+        if !time_manager.should_continue(&result, &game.turn, start_time.elapsed()) && !control.is_pondering() {
             break;
         }
     }
-    
-    best_move
-    
-}
\ No newline at end of file
+
+    // Not even the first iteration finished, any legal move is better than none.
+    if result.best_move.is_none() {
+        result.best_move = legal_moves.first().copied();
+    }
+    result.update_statistics(&start_time, &statistics);
+    result
+}
+
+/// Searches the root inside a window around the score of the previous iteration, widening the side
+/// that failed until the score falls inside it. Returns `None` when the time runs out.
+fn aspiration_search(
+    previous_score: f64,
+    depth: i8,
+    control: &SearchControl,
+    statistics: &SearchStatistics,
+    start_time: &Instant,
+    time_limit: Duration,
+    search: &mut dyn FnMut(f64, f64) -> f64,
+) -> Option<f64> {
+    let mut delta = ASPIRATION_WINDOW;
+    let (mut alpha, mut beta) = if depth > ASPIRATION_MIN_DEPTH && !eval::is_mate_score(previous_score) {
+        (eval::add_margin(previous_score, -delta), eval::add_margin(previous_score, delta))
+    } else {
+        (-100.0, 100.0)
+    };
+
+    loop {
+        let score = search(alpha, beta);
+
+        if control.should_stop(start_time, time_limit) {
+            return None;
+        }
+
+        delta *= 2;
+        if score <= alpha && alpha > -100.0 {
+            alpha = if delta > ASPIRATION_MAX_WINDOW { -100.0 } else { eval::add_margin(previous_score, -delta) };
+        } else if score >= beta && beta < 100.0 {
+            beta = if delta > ASPIRATION_MAX_WINDOW { 100.0 } else { eval::add_margin(previous_score, delta) };
+        } else {
+            return Some(score);
+        }
+        statistics.aspiration_researches.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -1,18 +1,59 @@
-use std::fs::File;
-use std::io::BufReader;
+use std::error::Error;
+use std::fmt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
-use crate::alpha_beta_search;
+use crate::alpha_beta_search::{self, SearchControl, SearchResult};
+use crate::bitboard;
+use crate::engine::Engine;
 use crate::fen_reader;
 use crate::fen_writer;
+use crate::game;
 use crate::make_move;
-use crate::model;
 use crate::move_gen;
+use crate::notation;
 use crate::piece;
+use crate::session::Session;
+use crate::uci;
+use async_std::channel::{self, Receiver, Sender};
+use async_std::task;
 use http_types::convert::json;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tide;
 
+const DEFAULT_ANALYSIS_TIME: Duration = Duration::from_secs(1);
+/// Longest `/analyze` search, also when only a depth is asked for, and longest engine move.
+const MAX_ANALYSIS_TIME: Duration = Duration::from_secs(60);
+/// How long a live analysis waits for its event stream to be opened before it is forgotten.
+const ANALYSIS_OPEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shared by every request, so the transposition table is allocated once when the server starts
+/// instead of for every Fen parsed. It also holds the games played through `/games` and the live
+/// analyses of `/analysis`.
+#[derive(Clone, Default)]
+pub struct State {
+    engine: Arc<Mutex<Engine>>,
+    sessions: Arc<Mutex<HashMap<u64, Session>>>,
+    analyses: Arc<Mutex<HashMap<u64, LiveAnalysis>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl State {
+    /// State searching with `engine`, e.g. one built with a smaller transposition table.
+    pub fn new(engine: Engine) -> State {
+        State {
+            engine: Arc::new(Mutex::new(engine)),
+            sessions: Arc::default(),
+            analyses: Arc::default(),
+            next_id: Arc::default(),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 struct Fen {
     fen: String,
@@ -24,12 +65,136 @@ struct Move {
     destiny: i8,
 }
 
-#[derive(Serialize, Deserialize)]
-struct MoveFen {
-    origin: i8,
-    destiny: i8,
-    promotion: String,
+/// Query of `/makeMove`: the move is given either as 64-square indices and a promotion letter, in
+/// UCI notation like `e7e8q` or in SAN like `Nf3`.
+/// Body of `POST /games/{id}/moves` too, which plays on the position of the game and has no `fen`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct MoveFen {
+    #[serde(default)]
+    pub fen: String,
+    pub origin: Option<i8>,
+    pub destiny: Option<i8>,
+    #[serde(default)]
+    pub promotion: String,
+    pub uci: Option<String>,
+    pub san: Option<String>,
+}
+
+/// Why `/makeMove` rejected a request. Sent back as `{"error": code, "message": text}`, with a 400
+/// status when the request is malformed and a 422 when the move is well formed but illegal.
+#[derive(Debug, PartialEq)]
+pub enum MoveError {
+    InvalidQuery(String),
+    InvalidFen(fen_reader::FenError),
+    MissingMove,
+    InvalidSquare(i8),
+    InvalidPromotion(String),
+    InvalidUci(String),
+    EmptySquare(String),
+    OpponentPiece(String),
+    Unreachable(String),
+    KingInCheck(String),
+    MissingPromotion(String),
+    UnexpectedPromotion(String),
+    NoSanMatch(String),
+}
+
+impl MoveError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            MoveError::InvalidQuery(_) => "invalid_query",
+            MoveError::InvalidFen(_) => "invalid_fen",
+            MoveError::MissingMove => "missing_move",
+            MoveError::InvalidSquare(_) => "invalid_square",
+            MoveError::InvalidPromotion(_) => "invalid_promotion",
+            MoveError::InvalidUci(_) => "invalid_uci",
+            MoveError::EmptySquare(_) => "empty_square",
+            MoveError::OpponentPiece(_) => "opponent_piece",
+            MoveError::Unreachable(_) => "unreachable",
+            MoveError::KingInCheck(_) => "king_in_check",
+            MoveError::MissingPromotion(_) => "missing_promotion",
+            MoveError::UnexpectedPromotion(_) => "unexpected_promotion",
+            MoveError::NoSanMatch(_) => "no_san_match",
+        }
+    }
+
+    pub fn status(&self) -> tide::StatusCode {
+        match self {
+            MoveError::InvalidQuery(_)
+            | MoveError::InvalidFen(_)
+            | MoveError::MissingMove
+            | MoveError::InvalidSquare(_)
+            | MoveError::InvalidPromotion(_)
+            | MoveError::InvalidUci(_) => tide::StatusCode::BadRequest,
+            _ => tide::StatusCode::UnprocessableEntity,
+        }
+    }
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::InvalidQuery(err) => write!(f, "invalid query: {}", err),
+            MoveError::InvalidFen(err) => write!(f, "invalid fen: {}", err),
+            MoveError::MissingMove => write!(f, "give the move as origin and destiny, uci or san"),
+            MoveError::InvalidSquare(square) => write!(f, "square {} is not between 0 and 63", square),
+            MoveError::InvalidPromotion(letter) => write!(f, "cannot promote to '{}'", letter),
+            MoveError::InvalidUci(movement) => write!(f, "'{}' is not a move in UCI notation", movement),
+            MoveError::EmptySquare(movement) => write!(f, "{}: there is no piece on the origin square", movement),
+            MoveError::OpponentPiece(movement) => write!(f, "{}: the piece belongs to the side not to move", movement),
+            MoveError::Unreachable(movement) => write!(f, "{}: the piece cannot move there", movement),
+            MoveError::KingInCheck(movement) => write!(f, "{}: it leaves the king in check", movement),
+            MoveError::MissingPromotion(movement) => write!(f, "{}: a promotion piece is required", movement),
+            MoveError::UnexpectedPromotion(movement) => write!(f, "{}: the move is not a promotion", movement),
+            MoveError::NoSanMatch(san) => write!(f, "'{}' is not a legal and unambiguous move", san),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+#[derive(Serialize)]
+struct SearchInfo {
+    fen: String,
+    depth: i8,
+    seldepth: i8,
+    score: f64,
+    nodes: u64,
+    nps: u64,
+    time: u128,
+    pv: Vec<String>,
+    tt_hit_rate: f64,
+    aspiration_researches: u64,
+}
+
+#[derive(Deserialize, Default)]
+struct Analyze {
+    fen: String,
+    /// Milliseconds.
+    movetime: Option<u64>,
+    depth: Option<i8>,
+    multipv: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Analysis {
     fen: String,
+    bestmove: Option<String>,
+    bestmove_san: Option<String>,
+    depth: i8,
+    seldepth: i8,
+    nodes: u64,
+    nps: u64,
+    time: u128,
+    lines: Vec<AnalysisLine>,
+}
+
+#[derive(Serialize)]
+struct AnalysisLine {
+    multipv: usize,
+    score: uci::Score,
+    pv: Vec<String>,
+    pv_san: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -38,11 +203,11 @@ struct BestMove {
     depth: i64,
 }
 
-pub async fn get_moves(request: tide::Request<()>) -> tide::Result {
+pub async fn get_moves(request: tide::Request<State>) -> tide::Result {
     let fen: Fen = request.query()?;
-    let mut game = fen_reader::read_fen(&fen.fen);
+    let game = fen_reader::try_read_fen(&fen.fen).map_err(bad_request)?;
 
-    let moves = move_gen::move_gen(&mut game);
+    let moves = game.legal_moves();
     let mut api_moves: Vec<Move> = Vec::new();
 
     for pos in moves {
@@ -55,57 +220,670 @@ pub async fn get_moves(request: tide::Request<()>) -> tide::Result {
     Ok(json!(api_moves).into())
 }
 
-pub async fn make_move(request: tide::Request<()>) -> tide::Result {
-    let mut movement: MoveFen = request.query()?;
+pub async fn make_move(request: tide::Request<State>) -> tide::Result {
+    let query: MoveFen = match request.query() {
+        Ok(query) => query,
+        Err(err) => return Ok(move_error(MoveError::InvalidQuery(err.to_string()))),
+    };
+    let mut game_info = match fen_reader::try_read_fen(&query.fen) {
+        Ok(game) => game,
+        Err(err) => return Ok(move_error(MoveError::InvalidFen(err))),
+    };
+    let mut movement = match requested_move(&query, &mut game_info) {
+        Ok(movement) => movement,
+        Err(err) => return Ok(move_error(err)),
+    };
+
+    make_move::make_move(&mut game_info, &mut movement);
+    let fen: String = fen_writer::write_fen(&game_info);
+
+    Ok(json!(Fen { fen }).into())
+}
+
+/// The legal move described by `query` in `game`, or why there is none.
+pub fn requested_move(query: &MoveFen, game: &mut game::Position) -> Result<move_gen::Move, MoveError> {
+    if let Some(san) = &query.san {
+        return notation::find_move(san, game).ok_or_else(|| MoveError::NoSanMatch(san.clone()));
+    }
+
+    let (origin, destiny, promotion) = match (&query.uci, query.origin, query.destiny) {
+        (Some(movement), _, _) => uci::parse_uci(movement).ok_or_else(|| MoveError::InvalidUci(movement.clone()))?,
+        (None, Some(origin), Some(destiny)) => {
+            if let Some(square) = [origin, destiny].into_iter().find(|square| !(0..64).contains(square)) {
+                return Err(MoveError::InvalidSquare(square));
+            }
+            let promotion = match query.promotion.as_str() {
+                "" => None,
+                letter => Some(
+                    letter_to_piece(letter.to_string())
+                        .filter(|piece| !matches!(piece, piece::PieceType::Pawn | piece::PieceType::King))
+                        .ok_or_else(|| MoveError::InvalidPromotion(letter.to_string()))?,
+                ),
+            };
+            (board64_to_board120(origin), board64_to_board120(destiny), promotion)
+        }
+        _ => return Err(MoveError::MissingMove),
+    };
+
+    legal_move(game, origin, destiny, promotion)
+}
+
+/// Looks the move up in the legal moves and, when it is not there, finds out why.
+fn legal_move(
+    game: &game::Position,
+    origin: i8,
+    destiny: i8,
+    promotion: Option<piece::PieceType>,
+) -> Result<move_gen::Move, MoveError> {
+    let name = fen_writer::index_to_letter_pos(&origin) + &fen_writer::index_to_letter_pos(&destiny);
+    let candidates: Vec<move_gen::Move> = game
+        .legal_moves()
+        .into_iter()
+        .filter(|movement| movement.origin == origin && movement.destiny == destiny)
+        .collect();
 
-    let en_passant: Option<i8> = None;
-    let mut game_info = fen_reader::read_fen(&movement.fen);
+    if candidates.is_empty() {
+        return Err(match (game.board[origin as usize], game.turn) {
+            (piece::Piece::Empty, _) => MoveError::EmptySquare(name),
+            (piece::Piece::White(_), game::Color::Black) | (piece::Piece::Black(_), game::Color::White) => {
+                MoveError::OpponentPiece(name)
+            }
+            _ if is_pseudo_legal(game, origin, destiny) => MoveError::KingInCheck(name),
+            _ => MoveError::Unreachable(name),
+        });
+    }
 
-    let destiny_piece: piece::Piece;
+    match (candidates[0].promotion, promotion) {
+        (Some(_), None) => Err(MoveError::MissingPromotion(name)),
+        (None, Some(_)) => Err(MoveError::UnexpectedPromotion(name)),
+        _ => Ok(*candidates.iter().find(|movement| movement.promotion == promotion).unwrap()),
+    }
+}
 
-    movement.origin = board64_to_board120(movement.origin);
-    movement.destiny = board64_to_board120(movement.destiny);
+fn is_pseudo_legal(game: &game::Position, origin: i8, destiny: i8) -> bool {
+    [move_gen::MoveClass::Captures, move_gen::MoveClass::Quiets]
+        .into_iter()
+        .flat_map(|class| move_gen::pseudo_legal(game, class, 1 << bitboard::square(origin), !0))
+        .any(|movement| movement.destiny == destiny)
+}
+
+fn move_error(error: MoveError) -> tide::Response {
+    error_response(error.status(), error.code(), error.to_string())
+}
 
-    match en_passant {
-        Some(pos) => {
-            destiny_piece = game_info.board[pos as usize];
+fn error_response(status: tide::StatusCode, code: &str, message: String) -> tide::Response {
+    tide::Response::builder(status)
+        .body(json!({ "error": code, "message": message }))
+        .build()
+}
+
+/// `GET /getBest?fen=..&depth=..`: searches for `depth` milliseconds and returns the position
+/// after the best move.
+pub async fn get_best(request: tide::Request<State>) -> tide::Result {
+    let query: BestMove = request.query()?;
+    let movetime = match u64::try_from(query.depth) {
+        Ok(movetime) => Duration::from_millis(movetime),
+        Err(_) => {
+            let message = format!("the thinking time {} is negative", query.depth);
+            return Ok(error_response(tide::StatusCode::BadRequest, "invalid_depth", message));
         }
+    };
+    let mut game = fen_reader::try_read_fen(&query.fen).map_err(bad_request)?;
+
+    // On a blocking thread: the engine may be busy with another search for up to a minute.
+    let engine = request.state().engine.clone();
+    let (search, mut game) = task::spawn_blocking(move || {
+        let mut engine = engine.lock().unwrap();
+        let search = alpha_beta_search::iterative_deepening_time_limit(&mut game, &mut engine, 30, movetime);
+        (search, game)
+    })
+    .await;
+    let mut movement = match search.best_move {
+        Some(movement) => movement,
         None => {
-            destiny_piece = game_info.board[movement.destiny as usize];
+            let message = "the side to move has no legal move".to_string();
+            return Ok(error_response(tide::StatusCode::UnprocessableEntity, "no_legal_moves", message));
+        }
+    };
+    make_move::make_move(&mut game, &mut movement);
+    let fen = fen_writer::write_fen(&game);
+
+    Ok(json!(SearchInfo {
+        fen,
+        depth: search.depth,
+        seldepth: search.seldepth,
+        score: search.score,
+        nodes: search.nodes,
+        nps: search.nps,
+        time: search.time.as_millis(),
+        pv: search.pv.into_iter().map(uci::move_to_uci).collect(),
+        tt_hit_rate: search.tt_hit_rate,
+        aspiration_researches: search.aspiration_researches,
+    })
+    .into())
+}
+
+/// Why a `/games` request failed, sent back like a `MoveError`.
+#[derive(Debug, PartialEq)]
+pub enum SessionError {
+    InvalidBody(String),
+    InvalidFen(fen_reader::FenError),
+    UnknownGame(String),
+    GameOver(String),
+    NothingToUndo,
+    GameChanged,
+    Move(MoveError),
+}
+
+impl SessionError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SessionError::InvalidBody(_) => "invalid_body",
+            SessionError::InvalidFen(_) => "invalid_fen",
+            SessionError::UnknownGame(_) => "unknown_game",
+            SessionError::GameOver(_) => "game_over",
+            SessionError::NothingToUndo => "nothing_to_undo",
+            SessionError::GameChanged => "game_changed",
+            SessionError::Move(err) => err.code(),
+        }
+    }
+
+    pub fn status(&self) -> tide::StatusCode {
+        match self {
+            SessionError::InvalidBody(_) | SessionError::InvalidFen(_) => tide::StatusCode::BadRequest,
+            SessionError::UnknownGame(_) => tide::StatusCode::NotFound,
+            SessionError::GameOver(_) | SessionError::NothingToUndo | SessionError::GameChanged => {
+                tide::StatusCode::Conflict
+            }
+            SessionError::Move(err) => err.status(),
+        }
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::InvalidBody(err) => write!(f, "invalid body: {}", err),
+            SessionError::InvalidFen(err) => write!(f, "invalid fen: {}", err),
+            SessionError::UnknownGame(id) => write!(f, "there is no game {}", id),
+            SessionError::GameOver(result) => write!(f, "the game is over: {}", result),
+            SessionError::NothingToUndo => write!(f, "no move has been played"),
+            SessionError::GameChanged => write!(f, "the game changed while the engine was thinking"),
+            SessionError::Move(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for SessionError {}
+
+#[derive(Deserialize, Default)]
+struct NewGame {
+    fen: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EngineMove {
+    /// Thinking time in milliseconds, at most `MAX_ANALYSIS_TIME`.
+    movetime: u64,
+}
+
+impl Default for EngineMove {
+    fn default() -> Self {
+        EngineMove { movetime: 1000 }
+    }
+}
+
+#[derive(Serialize)]
+struct GameState {
+    id: u64,
+    fen: String,
+    moves: Vec<String>,
+    result: String,
+    pgn: String,
+}
+
+impl GameState {
+    fn new(id: u64, session: &Session) -> GameState {
+        GameState {
+            id,
+            fen: session.fen(),
+            moves: session.san_moves(),
+            result: session.pgn.result.clone(),
+            pgn: session.to_pgn(),
+        }
+    }
+}
+
+impl From<GameState> for tide::Response {
+    fn from(state: GameState) -> tide::Response {
+        json!(state).into()
+    }
+}
+
+/// `POST /games`, with an optional `{"fen": ...}` body to start from a position.
+pub async fn new_game(mut request: tide::Request<State>) -> tide::Result {
+    Ok(respond(async {
+        let body: NewGame = read_body(&mut request).await?;
+        let session = Session::new(body.fen.as_deref()).map_err(SessionError::InvalidFen)?;
+
+        let id = request.state().next_id.fetch_add(1, Ordering::Relaxed);
+        let state = GameState::new(id, &session);
+        request.state().sessions.lock().unwrap().insert(id, session);
+
+        Ok(tide::Response::builder(tide::StatusCode::Created).body(json!(state)).build())
+    }
+    .await))
+}
+
+/// `GET /games/{id}`
+pub async fn get_game(request: tide::Request<State>) -> tide::Result {
+    Ok(respond(with_session(&request, |id, session| Ok(GameState::new(id, session)))))
+}
+
+/// `POST /games/{id}/moves`, the move given like in `/makeMove`.
+pub async fn play_move(mut request: tide::Request<State>) -> tide::Result {
+    Ok(respond(async {
+        let query: MoveFen = read_body(&mut request).await?;
+
+        with_session(&request, |id, session| {
+            if session.is_over() {
+                return Err(SessionError::GameOver(session.pgn.result.clone()));
+            }
+            let movement = requested_move(&query, &mut session.position).map_err(SessionError::Move)?;
+            session.play(movement);
+            Ok(GameState::new(id, session))
+        })
+    }
+    .await))
+}
+
+/// `POST /games/{id}/engine-move`, with an optional `{"movetime": ms}` body. The games stay
+/// available while the engine thinks on a copy of the position, on a blocking thread so the
+/// other requests are still served.
+pub async fn engine_move(mut request: tide::Request<State>) -> tide::Result {
+    Ok(respond(async {
+        let body: EngineMove = read_body(&mut request).await?;
+        let mut position = with_session(&request, |_, session| match session.is_over() {
+            true => Err(SessionError::GameOver(session.pgn.result.clone())),
+            false => Ok(session.position.clone()),
+        })?;
+        let hash = position.hash;
+
+        let engine = request.state().engine.clone();
+        let movetime = Duration::from_millis(body.movetime).min(MAX_ANALYSIS_TIME);
+        let search = task::spawn_blocking(move || {
+            let mut engine = engine.lock().unwrap();
+            alpha_beta_search::iterative_deepening_time_limit(&mut position, &mut engine, 30, movetime)
+        })
+        .await;
+
+        // Moves may have been played or taken back meanwhile, the move found is only played on
+        // the position it was searched for.
+        with_session(&request, |id, session| match search.best_move {
+            Some(movement) if session.position.hash == hash && session.position.legal_moves().contains(&movement) => {
+                session.play(movement);
+                Ok(GameState::new(id, session))
+            }
+            _ => Err(SessionError::GameChanged),
+        })
+    }
+    .await))
+}
+
+/// `POST /games/{id}/undo`
+pub async fn undo_move(request: tide::Request<State>) -> tide::Result {
+    Ok(respond(with_session(&request, |id, session| {
+        session.undo().ok_or(SessionError::NothingToUndo)?;
+        Ok(GameState::new(id, session))
+    })))
+}
+
+/// Runs `f` on the game of the `id` route parameter while holding the lock on the games.
+fn with_session<T>(
+    request: &tide::Request<State>,
+    f: impl FnOnce(u64, &mut Session) -> Result<T, SessionError>,
+) -> Result<T, SessionError> {
+    let param = request.param("id").unwrap_or_default();
+    let id: u64 = param.parse().map_err(|_| SessionError::UnknownGame(param.to_string()))?;
+
+    let mut sessions = request.state().sessions.lock().unwrap();
+    let session = sessions.get_mut(&id).ok_or_else(|| SessionError::UnknownGame(param.to_string()))?;
+    f(id, session)
+}
+
+/// JSON body of the request, the default value when it is empty.
+async fn read_body<T: DeserializeOwned + Default>(request: &mut tide::Request<State>) -> Result<T, SessionError> {
+    let body = request.body_string().await.map_err(|err| SessionError::InvalidBody(err.to_string()))?;
+    if body.trim().is_empty() {
+        return Ok(T::default());
+    }
+    serde_json::from_str(&body).map_err(|err| SessionError::InvalidBody(err.to_string()))
+}
+
+fn respond<T: Into<tide::Response>>(result: Result<T, SessionError>) -> tide::Response {
+    match result {
+        Ok(response) => response.into(),
+        Err(err) => error_response(err.status(), err.code(), err.to_string()),
+    }
+}
+
+/// `GET /analyze?fen=..&movetime=..&depth=..&multipv=..`: searches until the depth or the time
+/// runs out, one second when neither is given, and returns the `multipv` best lines.
+pub async fn analyze(request: tide::Request<State>) -> tide::Result {
+    let query: Analyze = match request.query() {
+        Ok(query) => query,
+        Err(err) => return Ok(analysis_error(AnalysisError::InvalidQuery(err.to_string()))),
+    };
+    let job = match AnalysisJob::new(&query, DEFAULT_ANALYSIS_TIME) {
+        Ok(job) => job,
+        Err(err) => return Ok(analysis_error(err)),
+    };
+
+    // On a blocking thread: the search holds the engine and would stall the other requests.
+    let state = request.state().clone();
+    let analysis = task::spawn_blocking(move || {
+        let search = job.run(&state, Arc::new(SearchControl::new()), &mut |_| ());
+        Analysis::new(&search, &job.game)
+    })
+    .await;
+
+    Ok(json!(analysis).into())
+}
+
+/// Why `/analyze` or `/analysis` rejected a request, sent back like a `MoveError`.
+#[derive(Debug, PartialEq)]
+pub enum AnalysisError {
+    InvalidQuery(String),
+    InvalidFen(fen_reader::FenError),
+    InvalidDepth(i8),
+    InvalidMultiPv,
+    UnknownAnalysis(String),
+}
+
+impl AnalysisError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AnalysisError::InvalidQuery(_) => "invalid_query",
+            AnalysisError::InvalidFen(_) => "invalid_fen",
+            AnalysisError::InvalidDepth(_) => "invalid_depth",
+            AnalysisError::InvalidMultiPv => "invalid_multipv",
+            AnalysisError::UnknownAnalysis(_) => "unknown_analysis",
+        }
+    }
+
+    pub fn status(&self) -> tide::StatusCode {
+        match self {
+            AnalysisError::UnknownAnalysis(_) => tide::StatusCode::NotFound,
+            _ => tide::StatusCode::BadRequest,
+        }
+    }
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnalysisError::InvalidQuery(err) => write!(f, "invalid query: {}", err),
+            AnalysisError::InvalidFen(err) => write!(f, "invalid fen: {}", err),
+            AnalysisError::InvalidDepth(depth) => {
+                write!(f, "depth {} is not between 1 and {}", depth, game::MAX_DEPTH)
+            }
+            AnalysisError::InvalidMultiPv => write!(f, "multipv must be at least 1"),
+            AnalysisError::UnknownAnalysis(id) => write!(f, "there is no analysis {}", id),
+        }
+    }
+}
+
+impl Error for AnalysisError {}
+
+/// A position to analyse and the limits of the search, checked.
+struct AnalysisJob {
+    game: game::Position,
+    depth: i8,
+    time_limit: Duration,
+    multi_pv: usize,
+}
+
+impl AnalysisJob {
+    /// `default_time` is the time limit when neither a time nor a depth is given.
+    fn new(query: &Analyze, default_time: Duration) -> Result<AnalysisJob, AnalysisError> {
+        let game = fen_reader::try_read_fen(&query.fen).map_err(AnalysisError::InvalidFen)?;
+        let depth = query.depth.unwrap_or(game::MAX_DEPTH);
+        if !(1..=game::MAX_DEPTH).contains(&depth) {
+            return Err(AnalysisError::InvalidDepth(depth));
+        }
+        let multi_pv = query.multipv.unwrap_or(1);
+        if multi_pv == 0 {
+            return Err(AnalysisError::InvalidMultiPv);
         }
+        let time_limit = match (query.movetime, query.depth) {
+            (Some(movetime), _) => Duration::from_millis(movetime).min(MAX_ANALYSIS_TIME),
+            (None, Some(_)) => MAX_ANALYSIS_TIME,
+            (None, None) => default_time,
+        };
+
+        Ok(AnalysisJob { game, depth, time_limit, multi_pv })
     }
 
-    let promotion: Option<piece::PieceType> = letter_to_piece(movement.promotion);
+    /// Searches with the shared engine, interrupted by `control`, calling `on_iteration` after
+    /// every depth. The options and control of the engine are put back afterwards.
+    fn run(&self, state: &State, control: Arc<SearchControl>, on_iteration: &mut dyn FnMut(&SearchResult)) -> SearchResult {
+        let mut engine = state.engine.lock().unwrap();
+        let options = engine.options;
+        let previous_control = std::mem::replace(&mut engine.control, control);
+        engine.options.multi_pv = self.multi_pv;
+
+        let mut game = self.game.clone();
+        let search = alpha_beta_search::iterative_deepening_time_limit_info(&mut game, &mut engine, self.depth, self.time_limit, on_iteration);
 
-    let mut movement: move_gen::Move = move_gen::Move {
-        origin: movement.origin,
-        destiny: movement.destiny,
-        destiny_piece,
-        promotion,
+        engine.options = options;
+        engine.control = previous_control;
+        search
+    }
+}
+
+impl Analysis {
+    fn new(search: &SearchResult, game: &game::Position) -> Analysis {
+        let lines = search
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| AnalysisLine {
+                multipv: index + 1,
+                score: uci::side_to_move_score(line.score, &game.turn),
+                pv: line.pv.iter().copied().map(uci::move_to_uci).collect(),
+                pv_san: notation::san_line(&line.pv, game),
+            })
+            .collect();
+
+        Analysis {
+            fen: fen_writer::write_fen(game),
+            bestmove: search.best_move.map(uci::move_to_uci),
+            bestmove_san: search.best_move.map(|movement| notation::get_san(&movement, &mut game.clone())),
+            depth: search.depth,
+            seldepth: search.seldepth,
+            nodes: search.nodes,
+            nps: search.nps,
+            time: search.time.as_millis(),
+            lines,
+        }
+    }
+}
+
+/// What the event stream of a live analysis waits for: the progress of its search and the
+/// messages posted by the client.
+enum AnalysisEvent {
+    Iteration(Analysis),
+    Done(Analysis),
+    Position(Box<AnalysisJob>),
+    Stop,
+}
+
+/// The events of a live analysis and its first position.
+type PendingStream = (Receiver<AnalysisEvent>, AnalysisJob);
+
+/// A live analysis registered by `POST /analysis`, until its event stream ends.
+struct LiveAnalysis {
+    inbox: Sender<AnalysisEvent>,
+    /// Taken by the client that opens the event stream, there is only one.
+    stream: Arc<Mutex<Option<PendingStream>>>,
+}
+
+/// `POST /analysis`, with the body of `/analyze` as JSON. The search starts when
+/// `GET /analysis/{id}/events` is opened and runs for a minute unless limited. An analysis whose
+/// stream is not opened within `ANALYSIS_OPEN_TIMEOUT` is dropped.
+pub async fn new_analysis(mut request: tide::Request<State>) -> tide::Result {
+    let query: Analyze = match read_body(&mut request).await {
+        Ok(query) => query,
+        Err(err) => return Ok(error_response(err.status(), err.code(), err.to_string())),
+    };
+    let job = match AnalysisJob::new(&query, MAX_ANALYSIS_TIME) {
+        Ok(job) => job,
+        Err(err) => return Ok(analysis_error(err)),
     };
 
-    make_move::make_move(&mut game_info, &mut movement);
-    game_info.print_board();
-    let fen: String = fen_writer::write_fen(&game_info);
-    println!("{}", fen);
+    let (inbox, events) = channel::unbounded();
+    let id = request.state().next_id.fetch_add(1, Ordering::Relaxed);
+    let stream = Arc::new(Mutex::new(Some((events, job))));
+    let analysis = LiveAnalysis { inbox, stream: stream.clone() };
+    request.state().analyses.lock().unwrap().insert(id, analysis);
 
-    Ok(json!(Fen { fen }).into())
+    let state = request.state().clone();
+    task::spawn(async move {
+        task::sleep(ANALYSIS_OPEN_TIMEOUT).await;
+        // Still pending: taking it means the stream can no longer be opened.
+        if stream.lock().unwrap().take().is_some() {
+            state.analyses.lock().unwrap().remove(&id);
+        }
+    });
+
+    Ok(tide::Response::builder(tide::StatusCode::Created).body(json!({ "id": id })).build())
 }
 
-pub async fn get_best(request: tide::Request<()>) -> tide::Result {
-    let query: BestMove = request.query()?;
-    let depth = query.depth;
-    let mut game = fen_reader::read_fen(&query.fen);
+/// `GET /analysis/{id}/events`: Server-Sent Events, an `info` event with the lines of every
+/// completed depth and a `bestmove` event when a search ends. The stream ends with the search,
+/// unless a new position was posted meanwhile or before the `bestmove` was sent, which is
+/// searched next.
+pub async fn analysis_events(request: tide::Request<State>) -> tide::Result {
+    let param = request.param("id").unwrap_or_default().to_string();
+    let stream = param
+        .parse::<u64>()
+        .ok()
+        .and_then(|id| Some((id, request.state().analyses.lock().unwrap().get(&id)?.stream.clone())));
+    let (id, stream) = match stream {
+        Some((id, stream)) if stream.lock().unwrap().is_some() => (id, stream),
+        _ => return Ok(analysis_error(AnalysisError::UnknownAnalysis(param))),
+    };
 
-    let mut vs = tch::nn::VarStore::new(tch::Device::Cpu);
-    let net = model::model(vs.root());
-    //vs.load_from_stream(&mut BufReader::new(File::open("./final_weights/8_hidden168000.pt").unwrap())).unwrap();
+    Ok(tide::sse::upgrade(request, move |request: tide::Request<State>, sender| {
+        let stream = stream.lock().unwrap().take();
+        async move {
+            let (events, job) = match stream {
+                Some(stream) => stream,
+                None => return Ok(()),
+            };
+            let state = request.state();
+            let inbox = state.analyses.lock().unwrap().get(&id).map(|analysis| analysis.inbox.clone());
+            let mut control = inbox.map(|inbox| start_analysis(state, job, inbox));
+            let mut next: Option<Box<AnalysisJob>> = None;
 
-    let mut movement = alpha_beta_search::iterative_deepening_time_limit(&mut game, 30, Duration::from_millis(depth.try_into().unwrap())).0.unwrap();
-    make_move::make_move(&mut game, &mut movement);
-    let fen = fen_writer::write_fen(&game);
+            while let Some(running) = &control {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(_) => break,
+                };
+                match event {
+                    AnalysisEvent::Iteration(analysis) => {
+                        // The client went away.
+                        if sender.send("info", json!(analysis).to_string(), None).await.is_err() {
+                            next = None;
+                            running.stop();
+                        }
+                    }
+                    AnalysisEvent::Done(analysis) => {
+                        let sent = sender.send("bestmove", json!(analysis).to_string(), None).await;
+                        // Positions posted after the search ended wait behind `Done`. They are
+                        // read under the lock of the analyses, so the stream does not end with
+                        // one accepted but never searched.
+                        let mut analyses = state.analyses.lock().unwrap();
+                        while let Ok(event) = events.try_recv() {
+                            match event {
+                                AnalysisEvent::Position(job) => next = Some(job),
+                                AnalysisEvent::Stop => next = None,
+                                AnalysisEvent::Iteration(_) | AnalysisEvent::Done(_) => (),
+                            }
+                        }
+                        control = match (next.take(), analyses.get(&id), sent) {
+                            (Some(job), Some(analysis), Ok(())) => Some(start_analysis(state, *job, analysis.inbox.clone())),
+                            _ => {
+                                analyses.remove(&id);
+                                None
+                            }
+                        };
+                    }
+                    AnalysisEvent::Position(job) => {
+                        next = Some(job);
+                        running.stop();
+                    }
+                    AnalysisEvent::Stop => {
+                        next = None;
+                        running.stop();
+                    }
+                }
+            }
 
-    Ok(json!(Fen { fen }).into())
+            state.analyses.lock().unwrap().remove(&id);
+            Ok(())
+        }
+    }))
+}
+
+/// `POST /analysis/{id}/position`, with the body of `POST /analysis`: stops the search and
+/// analyses this position instead.
+pub async fn analysis_position(mut request: tide::Request<State>) -> tide::Result {
+    let query: Analyze = match read_body(&mut request).await {
+        Ok(query) => query,
+        Err(err) => return Ok(error_response(err.status(), err.code(), err.to_string())),
+    };
+    match AnalysisJob::new(&query, MAX_ANALYSIS_TIME) {
+        Ok(job) => Ok(post_analysis_event(&request, AnalysisEvent::Position(Box::new(job)))),
+        Err(err) => Ok(analysis_error(err)),
+    }
+}
+
+/// `POST /analysis/{id}/stop`: stops the search, which ends the event stream.
+pub async fn stop_analysis(request: tide::Request<State>) -> tide::Result {
+    Ok(post_analysis_event(&request, AnalysisEvent::Stop))
+}
+
+fn post_analysis_event(request: &tide::Request<State>, event: AnalysisEvent) -> tide::Response {
+    let param = request.param("id").unwrap_or_default();
+    let analyses = request.state().analyses.lock().unwrap();
+
+    match param.parse::<u64>().ok().and_then(|id| analyses.get(&id)) {
+        Some(analysis) if analysis.inbox.try_send(event).is_ok() => tide::Response::new(tide::StatusCode::Accepted),
+        _ => analysis_error(AnalysisError::UnknownAnalysis(param.to_string())),
+    }
+}
+
+fn analysis_error(error: AnalysisError) -> tide::Response {
+    error_response(error.status(), error.code(), error.to_string())
+}
+
+/// Searches `job` on its own thread, which waits for the shared engine, and reports to `inbox`.
+/// Returns the control that stops it.
+fn start_analysis(state: &State, job: AnalysisJob, inbox: Sender<AnalysisEvent>) -> Arc<SearchControl> {
+    let control = Arc::new(SearchControl::new());
+    let state = state.clone();
+    let search_control = control.clone();
+
+    thread::spawn(move || {
+        let search = job.run(&state, search_control, &mut |result| {
+            let _ = inbox.try_send(AnalysisEvent::Iteration(Analysis::new(result, &job.game)));
+        });
+        let _ = inbox.try_send(AnalysisEvent::Done(Analysis::new(&search, &job.game)));
+    });
+
+    control
 }
 
 pub fn board64_to_board120(pos: i8) -> i8 {
@@ -124,6 +902,10 @@ pub fn board120_to_board64(pos: i8) -> i8 {
     row
 }
 
+fn bad_request(err: fen_reader::FenError) -> tide::Error {
+    tide::Error::new(tide::StatusCode::BadRequest, err)
+}
+
 fn letter_to_piece(mut letter: String) -> Option<piece::PieceType> {
     letter = letter.to_ascii_uppercase();
 
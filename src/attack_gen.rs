@@ -2,7 +2,7 @@ use crate::game;
 use crate::move_gen::{DIAGONAL_SLIDING, KNIGHT_SLIDING, LATERAL_SLIDING};
 use crate::piece;
 
-pub fn attack_gen(game: &mut game::GameInfo, color: Option<&game::Color>) -> ([u8; 120], i8) {
+pub fn attack_gen(game: &mut game::Position, color: Option<&game::Color>) -> ([u8; 120], i8) {
     let mut attacks: [u8; 120] = [0; 120];
     let mut attacker_pos: i8 = 0;
 
@@ -223,3 +223,57 @@ fn king_moves(piece_list: &Vec<i8>, board: &[piece::Piece; 120], attacks: &mut [
         }
     }
 }
+
+/// Square and type of the cheapest piece of `color` attacking `square` on `board`, pawns first
+/// and the king last. Pins are not looked at.
+pub fn least_valuable_attacker(
+    board: &[piece::Piece; 120],
+    square: i8,
+    color: &game::Color,
+) -> Option<(i8, piece::PieceType)> {
+    let own = |piece_type| match color {
+        game::Color::White => piece::Piece::White(piece_type),
+        game::Color::Black => piece::Piece::Black(piece_type),
+    };
+    let pawn_offsets = match color {
+        game::Color::White => [-9, -11],
+        game::Color::Black => [9, 11],
+    };
+
+    for offset in pawn_offsets {
+        if board[(square + offset) as usize] == own(piece::PieceType::Pawn) {
+            return Some((square + offset, piece::PieceType::Pawn));
+        }
+    }
+    for offset in KNIGHT_SLIDING {
+        if board[(square + offset) as usize] == own(piece::PieceType::Knight) {
+            return Some((square + offset, piece::PieceType::Knight));
+        }
+    }
+
+    let sliders = [
+        (&DIAGONAL_SLIDING, piece::PieceType::Bishop),
+        (&LATERAL_SLIDING, piece::PieceType::Rook),
+        (&DIAGONAL_SLIDING, piece::PieceType::Queen),
+        (&LATERAL_SLIDING, piece::PieceType::Queen),
+    ];
+    for (directions, piece_type) in sliders {
+        for direction in directions {
+            let mut origin = square + direction;
+            while board[origin as usize] == piece::Piece::Empty {
+                origin += direction;
+            }
+            if board[origin as usize] == own(piece_type) {
+                return Some((origin, piece_type));
+            }
+        }
+    }
+
+    for offset in DIAGONAL_SLIDING.iter().chain(LATERAL_SLIDING.iter()) {
+        if board[(square + offset) as usize] == own(piece::PieceType::King) {
+            return Some((square + offset, piece::PieceType::King));
+        }
+    }
+
+    None
+}
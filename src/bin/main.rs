@@ -1,4 +1,4 @@
-use chess::{api, zobrist_hashing::HASH};
+use chess::api;
 use http_types::headers::HeaderValue;
 use std::env;
 use tide::security::{CorsMiddleware, Origin};
@@ -6,13 +6,9 @@ use tide::security::{CorsMiddleware, Origin};
 #[async_std::main]
 async fn main() -> tide::Result<()> {
 
-    unsafe {
-        HASH.randomize();
-    }
-
     env::set_var("RUST_BACKTRACE", "0");
 
-    let mut app = tide::new();
+    let mut app = tide::with_state(api::State::default());
 
     let cors = CorsMiddleware::new()
         .allow_methods("GET, POST, OPTIONS".parse::<HeaderValue>().unwrap())
@@ -24,6 +20,16 @@ async fn main() -> tide::Result<()> {
     app.at("/getMoves").get(api::get_moves);
     app.at("/makeMove").get(api::make_move);
     app.at("/getBest").get(api::get_best);
+    app.at("/analyze").get(api::analyze);
+    app.at("/analysis").post(api::new_analysis);
+    app.at("/analysis/:id/events").get(api::analysis_events);
+    app.at("/analysis/:id/position").post(api::analysis_position);
+    app.at("/analysis/:id/stop").post(api::stop_analysis);
+    app.at("/games").post(api::new_game);
+    app.at("/games/:id").get(api::get_game);
+    app.at("/games/:id/moves").post(api::play_move);
+    app.at("/games/:id/engine-move").post(api::engine_move);
+    app.at("/games/:id/undo").post(api::undo_move);
     app.listen("127.0.0.1:8080").await?;
 
     Ok(())
@@ -1,14 +1,11 @@
-use std::{env, io::BufRead, fs, io::{BufReader, Write}, time::Duration, process};
+use std::{env, io::BufRead, fs, io::{BufReader, Write}, time::Duration, process, thread};
 use regex;
-use chess::{model, zobrist_hashing::HASH, training_parser, fen_reader, alpha_beta_search::{alpha_beta_min, self, iterative_deepening_time_limit}, uci, suite, game};
+use chess::{model, training_parser, fen_reader, alpha_beta_search::{alpha_beta_min, self, iterative_deepening_time_limit, SearchOptions}, uci, suite, game};
 use tch;
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
     env::var("RUST_MIN_STACK").unwrap_or_else(|_| "167772160".to_string());
     
-    unsafe {
-        HASH.randomize();
-    }
     /* 
 
     let mut suites = suite::get_suites();
@@ -18,18 +15,20 @@ fn main() {
     model::train();
     */
 
-    rayon::ThreadPoolBuilder::new()
-    .stack_size(8388608)
-    .num_threads(12)
-    .build_global()
-    .unwrap();
+    // Threads of the search, as many as cores unless given as the first argument.
+    let threads = env::args()
+        .nth(1)
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |x| x.get()));
+    let options = SearchOptions { threads, ..SearchOptions::new() };
 
     //println!("{}", suite::test_engine("stockfish", Duration::from_millis(100)));
-    let mut game = game::GameInfo::new();
+    let mut game = game::Position::new();
     //println!("{:?}",iterative_deepening_time_limit(&mut game, 100, Duration::from_millis(10000)));
-    println!("{}", suite::test_model_net(None, &mut suite::get_suites(), 0, Duration::from_millis(100)));
-    println!("{}", suite::test_model_net(None, &mut suite::get_suites(), 0, Duration::from_millis(500)));
-    println!("{}", suite::test_model_net(None, &mut suite::get_suites(), 0, Duration::from_millis(1000)));
+    println!("{}", suite::test_model_net(None, &mut suite::get_suites(), 0, Duration::from_millis(100), SearchOptions { threads, ..SearchOptions::none() }));
+    println!("{}", suite::test_model_net(None, &mut suite::get_suites(), 0, Duration::from_millis(100), options));
+    println!("{}", suite::test_model_net(None, &mut suite::get_suites(), 0, Duration::from_millis(500), options));
+    println!("{}", suite::test_model_net(None, &mut suite::get_suites(), 0, Duration::from_millis(1000), options));
 
 
     
@@ -0,0 +1,274 @@
+use std::{
+    io::{self, BufRead},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use chess::{
+    alpha_beta_search::{self, SearchControl},
+    engine::Engine,
+    fen_reader, game, make_move, move_gen,
+    time_manager::TimeManager,
+    transposition_table, uci,
+};
+
+const ENGINE_NAME: &str = "chess";
+const ENGINE_AUTHOR: &str = "Alberto Leon Sanchez";
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const INFINITE: Duration = Duration::from_secs(60 * 60 * 24);
+const MAX_HASH_MB: usize = 65536;
+const MAX_THREADS: usize = 256;
+
+struct GoParams {
+    depth: i8,
+    movetime: Option<u64>,
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: u64,
+    binc: u64,
+    moves_to_go: Option<u32>,
+    infinite: bool,
+    ponder: bool,
+}
+
+struct Search {
+    control: Arc<SearchControl>,
+    thread: JoinHandle<()>,
+}
+
+fn main() {
+    let mut game = game::Position::new();
+    // Kept between the moves of a game, so the table and the history still help the next search.
+    let engine = Arc::new(Mutex::new(Engine::new()));
+    let mut search: Option<Search> = None;
+
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name {}", ENGINE_NAME);
+                println!("id author {}", ENGINE_AUTHOR);
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    transposition_table::DEFAULT_SIZE_MB,
+                    MAX_HASH_MB
+                );
+                println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+                println!("option name Ponder type check default false");
+                println!("uciok");
+            }
+            Some(&"isready") => println!("readyok"),
+            Some(&"setoption") => {
+                stop(&mut search);
+                set_option(&mut engine.lock().unwrap(), &tokens[1..]);
+            }
+            Some(&"ucinewgame") => {
+                stop(&mut search);
+                game = fen_reader::read_fen(START_POSITION);
+                engine.lock().unwrap().new_game();
+            }
+            Some(&"position") => {
+                stop(&mut search);
+                // The position only changes when the whole command is valid.
+                match read_position(&tokens[1..]) {
+                    Ok(position) => game = position,
+                    Err(err) => println!("info string {}", err),
+                }
+            }
+            Some(&"go") => {
+                stop(&mut search);
+                search = Some(go(&game, &engine, parse_go(&tokens[1..])));
+            }
+            Some(&"ponderhit") => {
+                if let Some(search) = search.as_ref() {
+                    search.control.ponderhit();
+                }
+            }
+            Some(&"stop") => stop(&mut search),
+            Some(&"quit") => {
+                stop(&mut search);
+                break;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn read_position(tokens: &[&str]) -> Result<game::Position, Box<dyn std::error::Error>> {
+    let moves_index = tokens.iter().position(|x| *x == "moves").unwrap_or(tokens.len());
+
+    let mut game = match tokens.first() {
+        Some(&"startpos") => fen_reader::read_fen(START_POSITION),
+        Some(&"fen") => fen_reader::try_read_fen(&tokens[1..moves_index].join(" "))?,
+        _ => return Err("Invalid position command".into()),
+    };
+
+    for movement in tokens.iter().skip(moves_index + 1) {
+        let mut movement = uci::uci_to_move(movement, &mut game)?;
+        make_move::make_move(&mut game, &mut movement);
+    }
+
+    Ok(game)
+}
+
+fn set_option(engine: &mut Engine, tokens: &[&str]) {
+    let value_index = tokens.iter().position(|x| *x == "value").unwrap_or(tokens.len());
+    let name = tokens.get(1..value_index).unwrap_or_default().join(" ");
+    let value = tokens.get(value_index + 1).copied().unwrap_or_default();
+
+    if name.eq_ignore_ascii_case("Hash") {
+        match value.parse::<usize>() {
+            Ok(size) => engine.set_hash(size.clamp(1, MAX_HASH_MB)),
+            Err(_) => println!("info string Invalid Hash value {}", value),
+        }
+    } else if name.eq_ignore_ascii_case("Threads") {
+        match value.parse::<usize>() {
+            Ok(threads) => engine.options.threads = threads.clamp(1, MAX_THREADS),
+            Err(_) => println!("info string Invalid Threads value {}", value),
+        }
+    }
+}
+
+fn parse_go(tokens: &[&str]) -> GoParams {
+    let mut params = GoParams {
+        depth: game::MAX_DEPTH,
+        movetime: None,
+        wtime: None,
+        btime: None,
+        winc: 0,
+        binc: 0,
+        moves_to_go: None,
+        infinite: false,
+        ponder: false,
+    };
+
+    let value = |index: usize| -> u64 {
+        tokens
+            .get(index + 1)
+            .and_then(|x| x.parse::<i64>().ok())
+            .unwrap_or(0)
+            .max(0) as u64
+    };
+
+    for (index, token) in tokens.iter().enumerate() {
+        match *token {
+            "depth" => params.depth = value(index).clamp(1, game::MAX_DEPTH as u64) as i8,
+            "movetime" => params.movetime = Some(value(index)),
+            "wtime" => params.wtime = Some(value(index)),
+            "btime" => params.btime = Some(value(index)),
+            "winc" => params.winc = value(index),
+            "binc" => params.binc = value(index),
+            "movestogo" => params.moves_to_go = Some(value(index).min(u32::MAX as u64) as u32),
+            "infinite" => params.infinite = true,
+            "ponder" => params.ponder = true,
+            _ => (),
+        }
+    }
+
+    params
+}
+
+fn time_manager(params: &GoParams, turn: &game::Color) -> TimeManager {
+    if params.infinite {
+        return TimeManager::fixed(INFINITE);
+    }
+    if let Some(movetime) = params.movetime {
+        return TimeManager::fixed(Duration::from_millis(movetime));
+    }
+
+    let (time, increment) = match turn {
+        game::Color::White => (params.wtime, params.winc),
+        game::Color::Black => (params.btime, params.binc),
+    };
+
+    match time {
+        Some(time) => TimeManager::new(Duration::from_millis(time), Duration::from_millis(increment), params.moves_to_go),
+        None => TimeManager::fixed(INFINITE),
+    }
+}
+
+/// Searches on its own thread so `stop` and `ponderhit` can still be read. `go ponder` searches the
+/// position after the expected reply without time limit until `ponderhit` starts the clock.
+fn go(game: &game::Position, engine: &Arc<Mutex<Engine>>, params: GoParams) -> Search {
+    let mut game = game.clone();
+    let mut time_manager = time_manager(&params, &game.turn);
+    let control = Arc::new(if params.ponder {
+        SearchControl::pondering()
+    } else {
+        SearchControl::new()
+    });
+
+    let thread = {
+        let control = control.clone();
+        let engine = engine.clone();
+
+        thread::spawn(move || {
+            let mut engine = engine.lock().unwrap();
+            engine.control = control.clone();
+            let turn = game.turn;
+            let transposition_table = engine.transposition_table.clone();
+
+            let result = alpha_beta_search::iterative_deepening_time_manager(
+                &mut game,
+                &mut engine,
+                params.depth,
+                &mut time_manager,
+                &mut |result| {
+                    let mut info = format!(
+                        "info depth {} seldepth {} score {} nodes {} nps {} time {} hashfull {}",
+                        result.depth,
+                        result.seldepth,
+                        uci::score_to_uci(result.score, &turn),
+                        result.nodes,
+                        result.nps,
+                        result.time.as_millis(),
+                        transposition_table.hashfull()
+                    );
+                    if !result.pv.is_empty() {
+                        info.push_str(" pv");
+                        for movement in result.pv.iter() {
+                            info.push(' ');
+                            info.push_str(&uci::move_to_uci(*movement));
+                        }
+                    }
+                    println!("{}", info);
+                },
+            );
+
+            // The GUI expects no bestmove before `stop` in infinite mode, nor before `ponderhit`
+            // or `stop` while pondering, even when the search finished on its own.
+            while (params.infinite || control.is_pondering()) && !control.is_stopped() {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            print_best_move(result.best_move, result.pv.get(1).copied());
+        })
+    };
+
+    Search { control, thread }
+}
+
+fn stop(search: &mut Option<Search>) {
+    if let Some(search) = search.take() {
+        search.control.stop();
+        search.thread.join().unwrap();
+    }
+}
+
+fn print_best_move(movement: Option<move_gen::Move>, ponder: Option<move_gen::Move>) {
+    match (movement, ponder) {
+        (Some(movement), Some(ponder)) => println!(
+            "bestmove {} ponder {}",
+            uci::move_to_uci(movement),
+            uci::move_to_uci(ponder)
+        ),
+        (Some(movement), None) => println!("bestmove {}", uci::move_to_uci(movement)),
+        (None, _) => println!("bestmove 0000"),
+    }
+}
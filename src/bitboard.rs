@@ -0,0 +1,255 @@
+use crate::eval;
+use crate::game;
+use crate::move_gen;
+use crate::piece::{Piece, PieceType};
+
+/// Rank and file steps of the ray directions. The first four go towards higher squares, so the
+/// closest blocker on them is the lowest bit, and the last four towards lower squares.
+const DIRECTIONS: [(i32, i32); 8] = [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (0, -1), (-1, -1), (-1, 1)];
+const ROOK_DIRECTIONS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_DIRECTIONS: [usize; 4] = [2, 3, 6, 7];
+const KNIGHT_STEPS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_STEPS: [(i32, i32); 8] = [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (0, -1), (-1, -1), (-1, 1)];
+const WHITE_PAWN_STEPS: [(i32, i32); 2] = [(1, -1), (1, 1)];
+const BLACK_PAWN_STEPS: [(i32, i32); 2] = [(-1, -1), (-1, 1)];
+
+const KNIGHT_ATTACKS: [u64; 64] = leaper_attacks(&KNIGHT_STEPS);
+const KING_ATTACKS: [u64; 64] = leaper_attacks(&KING_STEPS);
+/// Squares attacked by a pawn of each color, white first.
+const PAWN_ATTACKS: [[u64; 64]; 2] = [leaper_attacks(&WHITE_PAWN_STEPS), leaper_attacks(&BLACK_PAWN_STEPS)];
+const RAYS: [[u64; 64]; 8] = rays();
+
+const fn leaper_attacks(steps: &[(i32, i32)]) -> [u64; 64] {
+    let mut attacks = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut step = 0;
+        while step < steps.len() {
+            let rank = square as i32 / 8 + steps[step].0;
+            let file = square as i32 % 8 + steps[step].1;
+            if rank >= 0 && rank < 8 && file >= 0 && file < 8 {
+                attacks[square] |= 1 << (rank * 8 + file);
+            }
+            step += 1;
+        }
+        square += 1;
+    }
+    attacks
+}
+
+const fn rays() -> [[u64; 64]; 8] {
+    let mut rays = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let mut square = 0;
+        while square < 64 {
+            let mut rank = square as i32 / 8 + DIRECTIONS[direction].0;
+            let mut file = square as i32 % 8 + DIRECTIONS[direction].1;
+            while rank >= 0 && rank < 8 && file >= 0 && file < 8 {
+                rays[direction][square] |= 1 << (rank * 8 + file);
+                rank += DIRECTIONS[direction].0;
+                file += DIRECTIONS[direction].1;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    rays
+}
+
+/// Square from 0 (a1) to 63 (h8) of a mailbox index.
+pub fn square(index: i8) -> usize {
+    ((index / 10 - 2) * 8 + index % 10 - 1) as usize
+}
+
+/// Mailbox index of a square from 0 (a1) to 63 (h8).
+pub fn index(square: usize) -> i8 {
+    (square / 8 * 10 + 21 + square % 8) as i8
+}
+
+/// Squares attacked along `direction` until the first occupied one, included.
+fn ray_attacks(direction: usize, square: usize, occupied: u64) -> u64 {
+    let ray = RAYS[direction][square];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+
+    let blocker = if direction < 4 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    ray ^ RAYS[direction][blocker as usize]
+}
+
+pub fn rook_attacks(square: usize, occupied: u64) -> u64 {
+    ROOK_DIRECTIONS.iter().fold(0, |attacks, direction| attacks | ray_attacks(*direction, square, occupied))
+}
+
+pub fn bishop_attacks(square: usize, occupied: u64) -> u64 {
+    BISHOP_DIRECTIONS.iter().fold(0, |attacks, direction| attacks | ray_attacks(*direction, square, occupied))
+}
+
+pub fn knight_attacks(square: usize) -> u64 {
+    KNIGHT_ATTACKS[square]
+}
+
+pub fn king_attacks(square: usize) -> u64 {
+    KING_ATTACKS[square]
+}
+
+pub fn pawn_attacks(square: usize, color: &game::Color) -> u64 {
+    PAWN_ATTACKS[side(color)][square]
+}
+
+/// Squares strictly between `from` and `to` when they share a rank, file or diagonal, else none.
+pub fn between(from: usize, to: usize) -> u64 {
+    for ray in RAYS.iter() {
+        if ray[from] & 1 << to != 0 {
+            return ray[from] & !ray[to] & !(1 << to);
+        }
+    }
+    0
+}
+
+/// The squares set in `bits`, lowest first.
+pub fn squares(mut bits: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bits == 0 {
+            return None;
+        }
+        let square = bits.trailing_zeros() as usize;
+        bits &= bits - 1;
+        Some(square)
+    })
+}
+
+fn side(color: &game::Color) -> usize {
+    match color {
+        game::Color::White => 0,
+        game::Color::Black => 1,
+    }
+}
+
+/// Index of `piece` in `Bitboards::pieces`, the same order as `eval::MAT`.
+fn piece_index(piece: Piece) -> Option<usize> {
+    let (piece_type, offset) = match piece {
+        Piece::White(piece_type) => (piece_type, 0),
+        Piece::Black(piece_type) => (piece_type, eval::BK),
+        Piece::Empty | Piece::Outside => return None,
+    };
+    let index = match piece_type {
+        PieceType::King => eval::WK,
+        PieceType::Queen => eval::WQ,
+        PieceType::Rook => eval::WR,
+        PieceType::Knight => eval::WN,
+        PieceType::Bishop => eval::WB,
+        PieceType::Pawn => eval::WP,
+    };
+    Some(index + offset)
+}
+
+/// One bitboard per piece and color plus the occupancy of each color, kept in sync with the
+/// mailbox `Position::board` by `make_move` and `unmake_move`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bitboards {
+    pieces: [u64; 12],
+    colors: [u64; 2],
+}
+
+impl Bitboards {
+    pub fn new() -> Bitboards {
+        Bitboards::default()
+    }
+
+    pub fn from_board(board: &[Piece; 120]) -> Bitboards {
+        let mut bitboards = Bitboards::new();
+        for square in 0..64 {
+            bitboards.toggle(board[index(square) as usize], index(square));
+        }
+        bitboards
+    }
+
+    /// Adds `piece` on `index` or removes it if it was there.
+    pub fn toggle(&mut self, piece: Piece, index: i8) {
+        let bit = 1 << square(index);
+        if let Some(piece_index) = piece_index(piece) {
+            self.pieces[piece_index] ^= bit;
+            self.colors[piece_index / eval::BK] ^= bit;
+        }
+    }
+
+    /// Toggles every piece `movement` moves, so the same call makes and unmakes it. `piece` is the
+    /// one left on the destination, the promoted piece for promotions.
+    pub fn toggle_move(&mut self, movement: &move_gen::Move, piece: Piece, en_passant: bool) {
+        let moved = match (movement.promotion, piece) {
+            (Some(_), Piece::White(_)) => Piece::White(PieceType::Pawn),
+            (Some(_), Piece::Black(_)) => Piece::Black(PieceType::Pawn),
+            _ => piece,
+        };
+        self.toggle(moved, movement.origin);
+        self.toggle(piece, movement.destiny);
+
+        if en_passant {
+            let (captured, behind) = match piece {
+                Piece::White(_) => (Piece::Black(PieceType::Pawn), -10),
+                _ => (Piece::White(PieceType::Pawn), 10),
+            };
+            self.toggle(captured, movement.destiny + behind);
+        } else {
+            self.toggle(movement.destiny_piece, movement.destiny);
+        }
+
+        if matches!(piece, Piece::White(PieceType::King) | Piece::Black(PieceType::King))
+            && (movement.destiny - movement.origin).abs() == 2
+        {
+            let (rook_origin, rook_destiny) = match movement.destiny {
+                27 => (28, 26),
+                23 => (21, 24),
+                97 => (98, 96),
+                _ => (91, 94),
+            };
+            let rook = match piece {
+                Piece::White(_) => Piece::White(PieceType::Rook),
+                _ => Piece::Black(PieceType::Rook),
+            };
+            self.toggle(rook, rook_origin);
+            self.toggle(rook, rook_destiny);
+        }
+    }
+
+    pub fn pieces(&self, piece: Piece) -> u64 {
+        piece_index(piece).map_or(0, |piece_index| self.pieces[piece_index])
+    }
+
+    pub fn color(&self, color: &game::Color) -> u64 {
+        self.colors[side(color)]
+    }
+
+    pub fn occupied(&self) -> u64 {
+        self.colors[0] | self.colors[1]
+    }
+
+    /// Pieces of `color` attacking the mailbox `index` with the given occupancy, which lets
+    /// callers remove pieces to see the ones behind them.
+    pub fn attackers(&self, index: i8, color: &game::Color, occupied: u64) -> u64 {
+        let square = square(index);
+        let own = |piece_type| match color {
+            game::Color::White => self.pieces(Piece::White(piece_type)),
+            game::Color::Black => self.pieces(Piece::Black(piece_type)),
+        };
+        let diagonal = own(PieceType::Bishop) | own(PieceType::Queen);
+        let lateral = own(PieceType::Rook) | own(PieceType::Queen);
+
+        (pawn_attacks(square, &color.opposite_color()) & own(PieceType::Pawn))
+            | (knight_attacks(square) & own(PieceType::Knight))
+            | (king_attacks(square) & own(PieceType::King))
+            | (bishop_attacks(square, occupied) & diagonal)
+            | (rook_attacks(square, occupied) & lateral)
+    }
+
+    pub fn is_attacked(&self, index: i8, color: &game::Color) -> bool {
+        self.attackers(index, color, self.occupied()) != 0
+    }
+}
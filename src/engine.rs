@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use crate::alpha_beta_search::{SearchControl, SearchOptions, SearchStatistics};
+use crate::search_context::SearchContext;
+use crate::transposition_table::{self, TranspositionTable};
+
+/// Everything a search keeps besides the position: the transposition table, the move ordering
+/// heuristics, the options, the control that interrupts it and its counters. Built once and reused for every
+/// search of a game, while positions are parsed and cloned without allocating any table.
+#[derive(Debug)]
+pub struct Engine {
+    pub transposition_table: Arc<TranspositionTable>,
+    pub context: SearchContext,
+    pub options: SearchOptions,
+    pub control: Arc<SearchControl>,
+    pub statistics: Arc<SearchStatistics>,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::with_hash(transposition_table::DEFAULT_SIZE_MB)
+    }
+
+    /// Engine with a transposition table of `size_mb` megabytes.
+    pub fn with_hash(size_mb: usize) -> Engine {
+        Engine {
+            transposition_table: Arc::new(TranspositionTable::new(size_mb)),
+            context: SearchContext::new(),
+            options: SearchOptions::new(),
+            control: Arc::new(SearchControl::new()),
+            statistics: Arc::new(SearchStatistics::new()),
+        }
+    }
+
+    /// Engine of a Lazy SMP helper thread: it shares the transposition table and the counters and
+    /// learns its own move ordering.
+    pub(crate) fn helper(&self, control: Arc<SearchControl>) -> Engine {
+        Engine {
+            transposition_table: self.transposition_table.clone(),
+            context: SearchContext::new(),
+            options: self.options,
+            control,
+            statistics: self.statistics.clone(),
+        }
+    }
+
+    /// Replaces the transposition table, forgetting its entries.
+    pub fn set_hash(&mut self, size_mb: usize) {
+        self.transposition_table = Arc::new(TranspositionTable::new(size_mb));
+    }
+
+    /// Forgets what was learnt on the previous game.
+    pub fn new_game(&mut self) {
+        self.transposition_table.clear();
+        self.context.clear();
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
@@ -2,16 +2,15 @@ use tch::nn::ModuleT;
 
 use crate::api::board120_to_board64;
 use crate::attack_gen;
+use crate::engine::Engine;
 use crate::game;
 use crate::game::Color;
-use crate::game::GameInfo;
-use crate::make_move;
+use crate::game::Position;
 use crate::model;
 use crate::move_gen;
 use crate::move_gen::move_gen;
 use crate::piece;
 use crate::piece::Piece;
-use crate::unmake;
 
 pub const WK: usize = 0;
 pub const WQ: usize = 1;
@@ -37,6 +36,13 @@ pub const MAT: [[i32; 12]; 2] = [
 
 const MAX_POSITIONAL_SCORE: i32 = 150;
 
+/// Checkmate scores sit above every `tanh` evaluation and shrink by `MATE_STEP` per ply, so the
+/// search prefers the shortest mate and delays being mated as long as it can.
+pub const MATE_SCORE: f64 = 2.0;
+pub const MATE_STEP: f64 = 0.001;
+/// The search never goes deeper than `i8::MAX` plies, anything beyond this score is a mate.
+pub const MATE_THRESHOLD: f64 = MATE_SCORE - i8::MAX as f64 * MATE_STEP;
+
 const Q_PHASE_CONTRIBUTION: i32 = 4;
 const R_PHASE_CONTRIBUTION: i32 = 2;
 const B_PHASE_CONTRIBUTION: i32 = 1;
@@ -169,28 +175,30 @@ const FLIP: [usize; 64] = [
     8, 9, 10, 11, 12, 13, 14, 15, 0, 1, 2, 3, 4, 5, 6, 7,
 ];
 
-pub fn order_moves(moves: &mut Vec<move_gen::Move>, pv: &Vec<move_gen::Move>, game: &mut game::GameInfo, ply:i8) {
+/// Orders `moves` for the search: the pv move, the transposition table move, captures that do not
+/// lose material by SEE, killers, the counter move, the rest by history and losing captures last.
+pub fn order_moves(moves: &mut Vec<move_gen::Move>, pv: &Vec<move_gen::Move>, game: &mut game::Position, engine: &Engine, ply:i8) {
     let mut pvf: bool = false;
     let mut move_scores: Vec<(usize, &move_gen::Move)> = Vec::new();
 
-    let side = if game.turn == game::Color::White { 0 } else { 1 };
+    let entry = engine.transposition_table.probe(game.hash);
+    let context = &engine.context;
+    let counter_move = context.counter_move(ply);
 
     for movement in moves.iter_mut(){
 
-        make_move::make_move(game, movement);
-        let hash = game.hash;
-        let index =(hash % game::TRANSPOSITION_TABLE_SIZE) as usize;
-        unmake::unmake_move(game, *movement);
-        let killer_move = game.killer_move.lock().unwrap();
         let score = match *movement{
             _ if !pvf && pv.contains(movement) => {pvf=true; 1000000000},
-            _ if game.transposition_table.lock().unwrap()[index].zobrist_key == hash => 100000000,
-            _ if movement.promotion.is_some() => movement.promotion.unwrap().get_value() as usize + 10000000,
-            _ if movement.destiny_piece != piece::Piece::Empty => {(movement.destiny_piece.get_value() - game.board[movement.origin as usize].get_value() + 10000000) as usize},
-            _ if killer_move[ply as usize][0] == *movement || killer_move[ply as usize][1] == *movement => 100000,
-            _ => game.historic_heuristic.lock().unwrap()[side][movement.origin as usize][movement.destiny as usize] as usize
+            _ if entry.is_some_and(|entry| entry.is_best_move(movement)) => 100000000,
+            _ if is_capture(movement) => match see(game, movement) {
+                gain if gain >= 0 => 10000000 + gain as usize,
+                // Losing captures go behind every quiet move.
+                _ => 0,
+            },
+            _ if context.is_killer(movement, ply) => 100000,
+            _ if counter_move == Some(*movement) => 90000,
+            _ => context.history(&game.turn, movement) as usize + 1
         };
-        drop(killer_move);
 
         move_scores.push((score, movement));
     }
@@ -200,19 +208,92 @@ pub fn order_moves(moves: &mut Vec<move_gen::Move>, pv: &Vec<move_gen::Move>, ga
 }   
 
 
-pub fn check(game: &mut game::GameInfo, color: game::Color) -> bool {
+pub fn is_capture(movement: &move_gen::Move) -> bool {
+    movement.promotion.is_some() || movement.destiny_piece != piece::Piece::Empty
+}
+
+/// MVV-LVA score of a capture or promotion, always above the killer and history scores.
+pub fn capture_score(movement: &move_gen::Move, game: &game::Position) -> usize {
+    match movement.promotion {
+        Some(piece) => piece.get_value() as usize + 10000000,
+        None => (movement.destiny_piece.get_value() - game.board[movement.origin as usize].get_value() + 10000000) as usize,
+    }
+}
+
+/// Static exchange evaluation: material `movement` wins for the side to move, in `MAT` units,
+/// once both sides have recaptured on its destination with their cheapest piece for as long as it
+/// pays off. Sliders behind a piece that captures, x-rays, join the exchange when uncovered.
+pub fn see(game: &game::Position, movement: &move_gen::Move) -> i32 {
+    let mut board = game.board;
+    let target = movement.destiny;
+    let mut piece_type = match board[movement.origin as usize] {
+        Piece::White(piece_type) | Piece::Black(piece_type) => piece_type,
+        _ => return 0,
+    };
+
+    // gain[n]: material of the side making the n-th capture if the exchange stopped there.
+    let mut gain = [0; 32];
+    gain[0] = match movement.destiny_piece {
+        Piece::White(captured) | Piece::Black(captured) => captured.get_value(),
+        _ if piece_type == piece::PieceType::Pawn && (movement.destiny - movement.origin) % 10 != 0 => {
+            let captured = movement.destiny - (movement.destiny - movement.origin).signum() * 10;
+            board[captured as usize] = Piece::Empty;
+            piece::PieceType::Pawn.get_value()
+        }
+        _ => 0,
+    };
+    if let Some(promotion) = movement.promotion {
+        gain[0] += promotion.get_value() - piece::PieceType::Pawn.get_value();
+        piece_type = promotion;
+    }
+    board[movement.origin as usize] = Piece::Empty;
+
+    let mut color = match game.turn {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    };
+    let mut depth = 0;
+    while depth + 1 < gain.len() {
+        let (square, attacker) = match attack_gen::least_valuable_attacker(&board, target, &color) {
+            Some(attacker) => attacker,
+            None => break,
+        };
+        depth += 1;
+        gain[depth] = piece_type.get_value() - gain[depth - 1];
+        piece_type = attacker;
+        board[square as usize] = Piece::Empty;
+        color = match color {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        };
+    }
+
+    // Every side only makes its capture when it is better than stopping.
+    while depth > 0 {
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        depth -= 1;
+    }
+
+    gain[0]
+}
+
+pub fn order_captures(moves: &mut Vec<move_gen::Move>, game: &game::Position) {
+    moves.sort_unstable_by_key(|movement| std::cmp::Reverse(capture_score(movement, game)));
+}
+
+pub fn check(game: &mut game::Position, color: game::Color) -> bool {
     let (_, attacks) = attack_gen::attack_gen(game, Some(&color));
 
     attacks != 0
 }
 
-pub fn net_eval(game: &mut game::GameInfo, net: &model::Net) -> f64 {
+pub fn net_eval(game: &mut game::Position, net: &model::Net) -> f64 {
     net.forward_t(&model::pre_proccess(game), true)
             .double_value(&[0])
 
 }
 
-pub fn net_eval_tch(game: &mut game::GameInfo, net: &model::Net) -> tch::Tensor {
+pub fn net_eval_tch(game: &mut game::Position, net: &model::Net) -> tch::Tensor {
      net.forward_t(&model::pre_proccess(game), true)
 }
 
@@ -222,7 +303,7 @@ fn scale_phase(opening_score: i32, endgame_score: i32, phase: i32) -> i32 {
     endgame_score + diff * phase / MAX_PHASE
 }
 
-pub fn evaluate_material(game: &GameInfo) -> f64 {
+pub fn evaluate_material(game: &Position) -> f64 {
     let mut ret: i32 = 0;
 
     let wqcount = game.white_pieces.queens.len() as i32;
@@ -265,7 +346,7 @@ pub fn evaluate_material(game: &GameInfo) -> f64 {
     (1e-3 * ret as f64).tanh()
 }
 
-fn evaluate_pawns(game: &GameInfo, phase: i32) -> i32 {
+fn evaluate_pawns(game: &Position, phase: i32) -> i32 {
     let mut ret = 0;
 
     for white in &game.white_pieces.pawns {
@@ -290,7 +371,7 @@ fn evaluate_pawns(game: &GameInfo, phase: i32) -> i32 {
 }
 
 fn evaluate_knights(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -323,7 +404,7 @@ fn evaluate_knights(
 }
 
 fn evaluate_bishops(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -364,7 +445,7 @@ fn evaluate_bishops(
 }
 
 fn evaluate_rooks(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -397,7 +478,7 @@ fn evaluate_rooks(
 }
 
 fn evaluate_queens(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -429,7 +510,7 @@ fn evaluate_queens(
     ret
 }
 
-fn evaluate_kings(game: &GameInfo, phase: i32) -> i32 {
+fn evaluate_kings(game: &Position, phase: i32) -> i32 {
     let mut ret = 0;
 
     let index = board120_to_board64(game.white_pieces.kings[0]) as usize;
@@ -441,7 +522,7 @@ fn evaluate_kings(game: &GameInfo, phase: i32) -> i32 {
     ret
 }
 
-pub fn static_evaluate(game: &mut GameInfo) -> f64 {
+pub fn static_evaluate(game: &mut Position) -> f64 {
     
     if let Some(value) = is_game_over(game) {
         return value;
@@ -622,22 +703,103 @@ pub fn static_evaluate(game: &mut GameInfo) -> f64 {
     (1e-3 * ret as f64).tanh()
 }
 
-pub fn is_game_over(game: &mut GameInfo) -> Option<f64> {
-    if move_gen::move_gen(game).len() == 0{
-        if game.turn == game::Color::White{
-            if check(game, game::Color::Black){
-                return Some(-1.0)
-            }else{
-                return Some(0.0)
-            }
-        }else{
-            if check(game, game::Color::White){
-                return Some(1.0)
-            }else{
-                return Some(0.0)
-            }
-        }
+/// Moves a `tanh` squashed score by `margin` evaluation units, as if it had been added before
+/// squashing.
+pub fn add_margin(score: f64, margin: i32) -> f64 {
+    (score.clamp(-0.9999, 0.9999).atanh() + 1e-3 * margin as f64).tanh()
+}
+
+pub fn is_game_over(game: &mut Position) -> Option<f64> {
+    if game.is_checkmate() {
+        return match game.turn {
+            game::Color::White => Some(-1.0),
+            game::Color::Black => Some(1.0),
+        };
+    }
+    if game.is_stalemate() {
+        return Some(0.0)
+    }
+    if is_draw(game, 3) {
+        return Some(0.0)
     }
     None
 }
 
+/// Score of a position without legal moves found at `ply` of the search, the root being ply 1.
+pub fn terminal_score(game: &mut Position, ply: i8) -> f64 {
+    if !game.in_check() {
+        return 0.0;
+    }
+
+    let mate = MATE_SCORE - ply as f64 * MATE_STEP;
+    match game.turn {
+        Color::White => -mate,
+        Color::Black => mate,
+    }
+}
+
+pub fn is_mate_score(score: f64) -> bool {
+    score.abs() >= MATE_THRESHOLD && score.abs() <= MATE_SCORE
+}
+
+/// Plies from the root to the mate announced by a search score.
+pub fn mate_distance(score: f64) -> i8 {
+    ((MATE_SCORE - score.abs()) / MATE_STEP).round() as i8 - 1
+}
+
+/// Draw by repetition, fifty-move rule or insufficient material. The search passes
+/// `repetitions = 2` so any position repeated inside the tree already counts as a draw.
+pub fn is_draw(game: &Position, repetitions: usize) -> bool {
+    is_repetition(game, repetitions) || is_fifty_move_rule(game) || is_insufficient_material(game)
+}
+
+pub fn is_repetition(game: &Position, repetitions: usize) -> bool {
+    let reversible_moves = *game.half_move_clock.last().unwrap() as usize;
+
+    let occurrences = game
+        .history
+        .iter()
+        .rev()
+        .take(reversible_moves)
+        .skip(1)
+        .step_by(2)
+        .filter(|hash| **hash == game.hash)
+        .count();
+
+    occurrences + 1 >= repetitions
+}
+
+pub fn is_fifty_move_rule(game: &Position) -> bool {
+    *game.half_move_clock.last().unwrap() >= 100
+}
+
+pub fn is_insufficient_material(game: &Position) -> bool {
+    let white = &game.white_pieces;
+    let black = &game.black_pieces;
+
+    if !white.pawns.is_empty() || !black.pawns.is_empty() {
+        return false;
+    }
+    if !white.rooks.is_empty() || !black.rooks.is_empty() {
+        return false;
+    }
+    if !white.queens.is_empty() || !black.queens.is_empty() {
+        return false;
+    }
+
+    let white_minors = white.knights.len() + white.bishops.len();
+    let black_minors = black.knights.len() + black.bishops.len();
+
+    if white_minors + black_minors <= 1 {
+        return true;
+    }
+
+    let square_color = |pos: i8| (pos / 10 + pos % 10) % 2;
+
+    white_minors == 1
+        && black_minors == 1
+        && white.bishops.len() == 1
+        && black.bishops.len() == 1
+        && square_color(white.bishops[0]) == square_color(black.bishops[0])
+}
+
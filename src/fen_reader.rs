@@ -1,9 +1,8 @@
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::error::Error;
+use std::fmt;
 
-use crate::eval;
+use crate::bitboard::Bitboards;
 use crate::game;
-use crate::move_gen;
 use crate::piece::Piece;
 use crate::piece::PieceList;
 use crate::piece::PieceType;
@@ -14,116 +13,161 @@ pub const ROW_SIZE: u32 = 10;
 pub const ROW_OFF_SET: u32 = 1;
 const DECIMAL_RADIX: u32 = 10;
 
-pub fn read_fen(fen: &str) -> game::GameInfo {
-    let split: Vec<&str> = fen.split(' ').collect();
-    let mut board: [Piece; 120] = [Piece::Outside; 120];
+#[derive(Debug, PartialEq)]
+pub enum FenError {
+    FieldCount(usize),
+    RankCount(usize),
+    RankLength { rank: usize, length: u32 },
+    UnknownPiece(char),
+    InvalidTurn(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidClock(String),
+    MissingKing(game::Color),
+    TooManyKings(game::Color),
+    OpponentInCheck,
+}
 
-    complete_board(&mut board);
-    set_pieces(&mut board, split[0]);
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::FieldCount(count) => write!(f, "Expected 4 to 6 fields in Fen, found {}", count),
+            FenError::RankCount(count) => write!(f, "Expected 8 ranks in Fen, found {}", count),
+            FenError::RankLength { rank, length } => {
+                write!(f, "Rank {} of the Fen has {} squares", rank, length)
+            }
+            FenError::UnknownPiece(piece) => write!(f, "Piece not recognized in Fen: {}", piece),
+            FenError::InvalidTurn(turn) => write!(f, "Invalid color in Fen: {}", turn),
+            FenError::InvalidCastling(castling) => write!(f, "Invalid castling rights in Fen: {}", castling),
+            FenError::InvalidEnPassant(square) => write!(f, "Invalid en passant square in Fen: {}", square),
+            FenError::InvalidClock(clock) => write!(f, "Invalid move clock in Fen: {}", clock),
+            FenError::MissingKing(color) => write!(f, "No {:?} king in Fen", color),
+            FenError::TooManyKings(color) => write!(f, "More than one {:?} king in Fen", color),
+            FenError::OpponentInCheck => write!(f, "The side not to move is in check"),
+        }
+    }
+}
 
-    let (white_pieces, black_pieces) = get_piece_lists(&board);
+impl Error for FenError {}
 
-    let turn = get_turn(split[1]);
+/// Same as `read_fen` but returns an error instead of panicking on malformed or illegal input.
+pub fn try_read_fen(fen: &str) -> Result<game::Position, FenError> {
+    let fen = validate_fen(fen)?;
+    Ok(read_fen(&fen))
+}
 
-    let castling = get_castling(split[2]);
+/// Checks that `fen` describes a legal position and returns it with normalized whitespace, ready
+/// for `read_fen`.
+pub fn validate_fen(fen: &str) -> Result<String, FenError> {
+    let mut split: Vec<&str> = fen.split_whitespace().collect();
 
-    let en_passant = get_en_passant(split[3]);
+    if split.len() < 4 || split.len() > 6 {
+        return Err(FenError::FieldCount(split.len()));
+    }
 
-    let half_move_clock = get_half_move_clock(split[4]);
+    validate_pieces(split[0])?;
 
-    let full_move;
-    if split.len() == 6 {
-        full_move = get_full_move(split[5]);
-    } else {
-        full_move = 0;
+    if split[1] != "w" && split[1] != "b" {
+        return Err(FenError::InvalidTurn(split[1].to_string()));
     }
-    let hash;
-
-    unsafe {
-        hash = HASH.get_hash(
-            &black_pieces,
-            &white_pieces,
-            &turn,
-            &castling.last().unwrap(),
-            &en_passant.last().unwrap(),
-        );
+
+    let castling = split[2];
+    if castling != "-"
+        && (castling.is_empty()
+            || castling.len() > 4
+            || !castling.chars().all(|x| "KQkq".contains(x))
+            || castling.chars().enumerate().any(|(i, x)| castling[i + 1..].contains(x)))
+    {
+        return Err(FenError::InvalidCastling(castling.to_string()));
     }
 
-    game::GameInfo {
-        board,
-        white_pieces,
-        black_pieces,
-        turn,
-        castling,
-        en_passant,
-        half_move_clock,
-        full_move,
-        hash,
-        transposition_table: std::sync::Arc::new(std::sync::Mutex::new(vec![game::Eval::new(); (game::TRANSPOSITION_TABLE_SIZE) as usize])),
-        historic_heuristic: std::sync::Arc::new(std::sync::Mutex::new([[[0; 120]; 120]; 2])),
-        killer_move: std::sync::Arc::new(std::sync::Mutex::new([[move_gen::Move::new(); 2]; 20])),
+    let en_passant = split[3];
+    let en_passant_rank = if split[1] == "w" { '6' } else { '3' };
+    if en_passant != "-"
+        && !(en_passant.len() == 2
+            && ('a'..='h').contains(&en_passant.chars().next().unwrap())
+            && en_passant.chars().nth(1).unwrap() == en_passant_rank)
+    {
+        return Err(FenError::InvalidEnPassant(en_passant.to_string()));
     }
-}
 
-pub fn read_fen_share_tt(fen: &str, tt: Arc<Mutex<Vec<game::Eval>>>) -> game::GameInfo{
-    let split: Vec<&str> = fen.split(' ').collect();
-    let mut board: [Piece; 120] = [Piece::Outside; 120];
+    for clock in split.iter().skip(4) {
+        if clock.parse::<u16>().is_err() {
+            return Err(FenError::InvalidClock(clock.to_string()));
+        }
+    }
 
-    complete_board(&mut board);
-    set_pieces(&mut board, split[0]);
+    if split.len() == 4 {
+        split.push("0");
+    }
+    if split.len() == 5 {
+        split.push("1");
+    }
 
-    let (white_pieces, black_pieces) = get_piece_lists(&board);
+    let fen = split.join(" ");
+    let mut game = read_fen(&fen);
+
+    for (color, pieces) in [
+        (game::Color::White, &game.white_pieces),
+        (game::Color::Black, &game.black_pieces),
+    ] {
+        match pieces.kings.len() {
+            0 => return Err(FenError::MissingKing(color)),
+            1 => (),
+            _ => return Err(FenError::TooManyKings(color)),
+        }
+    }
 
-    let turn = get_turn(split[1]);
+    let rights = game.castling[0];
+    let rook_and_king = |king: usize, rook: usize, piece: fn(PieceType) -> Piece| {
+        game.board[king] == piece(PieceType::King) && game.board[rook] == piece(PieceType::Rook)
+    };
+    if (rights[0] && !rook_and_king(25, 28, Piece::White))
+        || (rights[1] && !rook_and_king(25, 21, Piece::White))
+        || (rights[2] && !rook_and_king(95, 98, Piece::Black))
+        || (rights[3] && !rook_and_king(95, 91, Piece::Black))
+    {
+        return Err(FenError::InvalidCastling(castling.to_string()));
+    }
 
-    let castling = get_castling(split[2]);
+    game.turn = game.turn.change_turn();
+    if game.in_check() {
+        return Err(FenError::OpponentInCheck);
+    }
 
-    let en_passant = get_en_passant(split[3]);
+    Ok(fen)
+}
 
-    let half_move_clock = get_half_move_clock(split[4]);
+fn validate_pieces(fen: &str) -> Result<(), FenError> {
+    let ranks: Vec<&str> = fen.split('/').collect();
 
-    let full_move;
-    if split.len() == 6 {
-        full_move = get_full_move(split[5]);
-    } else {
-        full_move = 0;
-    }
-    let hash;
-
-    unsafe {
-        hash = HASH.get_hash(
-            &black_pieces,
-            &white_pieces,
-            &turn,
-            &castling.last().unwrap(),
-            &en_passant.last().unwrap(),
-        );
+    if ranks.len() != 8 {
+        return Err(FenError::RankCount(ranks.len()));
     }
 
-    game::GameInfo {
-        board,
-        white_pieces,
-        black_pieces,
-        turn,
-        castling,
-        en_passant,
-        half_move_clock,
-        full_move,
-        hash,
-        transposition_table: tt,
-        historic_heuristic: std::sync::Arc::new(std::sync::Mutex::new([[[0; 120]; 120]; 2])),
-        killer_move: std::sync::Arc::new(std::sync::Mutex::new([[move_gen::Move::new(); 2]; 20])),
-    }
-}
+    for (index, rank) in ranks.iter().enumerate() {
+        let mut length = 0;
 
-pub fn invalidate_tt(game: &mut game::GameInfo){
-    let mut tt = game.transposition_table.lock().unwrap();
-    for i in 0..tt.len(){
-        tt[i] = game::Eval::new();
+        for character in rank.chars() {
+            match character {
+                '1'..='8' => length += character.to_digit(DECIMAL_RADIX).unwrap(),
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => length += 1,
+                _ => return Err(FenError::UnknownPiece(character)),
+            }
+        }
+
+        if length != 8 {
+            return Err(FenError::RankLength {
+                rank: 8 - index,
+                length,
+            });
+        }
     }
+
+    Ok(())
 }
 
-pub fn read_fen_no_tt(fen: &str) -> game::GameInfo {
+pub fn read_fen(fen: &str) -> game::Position {
     let split: Vec<&str> = fen.split(' ').collect();
     let mut board: [Piece; 120] = [Piece::Outside; 120];
 
@@ -146,20 +190,17 @@ pub fn read_fen_no_tt(fen: &str) -> game::GameInfo {
     } else {
         full_move = 0;
     }
-    let hash;
-
-    unsafe {
-        hash = HASH.get_hash(
-            &black_pieces,
-            &white_pieces,
-            &turn,
-            &castling.last().unwrap(),
-            &en_passant.last().unwrap(),
-        );
-    }
-
-    game::GameInfo {
+    let hash = HASH.get_hash(
+        &black_pieces,
+        &white_pieces,
+        &turn,
+        &castling.last().unwrap(),
+        &en_passant.last().unwrap(),
+    );
+
+    game::Position {
         board,
+        bitboards: Bitboards::from_board(&board),
         white_pieces,
         black_pieces,
         turn,
@@ -168,9 +209,7 @@ pub fn read_fen_no_tt(fen: &str) -> game::GameInfo {
         half_move_clock,
         full_move,
         hash,
-        transposition_table: std::sync::Arc::new(std::sync::Mutex::new(vec![game::Eval::new(); 1])),
-        historic_heuristic: std::sync::Arc::new(std::sync::Mutex::new([[[0; 120]; 120]; 2])),
-        killer_move: std::sync::Arc::new(std::sync::Mutex::new([[move_gen::Move::new(); 2]; 20])),
+        history: Vec::new(),
     }
 }
 
@@ -325,42 +364,3 @@ pub fn letter_to_column(letter: char) -> u32 {
 pub fn row_column_to_index(row: &u32, column: &u32) -> usize {
     (row * ROW_SIZE + MAILBOX_OFF_SET + ROW_OFF_SET + column) as usize
 }
-
-pub fn read_fen_keep_transposition_table(fen: &str, game: &mut game::GameInfo) {
-    
-    let split: Vec<&str> = fen.split(' ').collect();
-    game.board = [Piece::Outside; 120];
-
-    complete_board(&mut game.board);
-    set_pieces(&mut game.board, split[0]);
-
-    let (white_pieces, black_pieces) = get_piece_lists(&game.board);
-
-    game.white_pieces = white_pieces;
-    game.black_pieces = black_pieces;
-
-    game.turn = get_turn(split[1]);
-
-    game.castling = get_castling(split[2]);
-
-    game.en_passant = get_en_passant(split[3]);
-
-    game.half_move_clock = get_half_move_clock(split[4]);
-
-    if split.len() == 6 {
-        game.full_move = get_full_move(split[5]);
-    } else {
-        game.full_move = 0;
-    }
-
-    unsafe {
-        game.hash = HASH.get_hash(
-            &game.black_pieces,
-            &game.white_pieces,
-            &game.turn,
-            &game.castling.last().unwrap(),
-            &game.en_passant.last().unwrap(),
-        );
-    }
-     
-}
@@ -2,7 +2,7 @@ use crate::fen_reader;
 use crate::game;
 use crate::piece;
 
-pub fn write_fen(game_info: &game::GameInfo) -> String {
+pub fn write_fen(game_info: &game::Position) -> String {
     let mut fen = String::new();
     let mut count = 0;
 
@@ -76,14 +76,14 @@ pub fn piece_to_letter(mut fen: String, piece: &piece::PieceType, white: bool) -
     fen
 }
 
-fn get_turn(game_info: &game::GameInfo) -> char {
+fn get_turn(game_info: &game::Position) -> char {
     match game_info.turn {
         game::Color::White => 'w',
         game::Color::Black => 'b',
     }
 }
 
-fn get_castling(game_info: &game::GameInfo) -> String {
+fn get_castling(game_info: &game::Position) -> String {
     let mut castling = String::new();
     let rights = game_info.castling.last().unwrap();
 
@@ -106,7 +106,7 @@ fn get_castling(game_info: &game::GameInfo) -> String {
     castling
 }
 
-fn get_enpassent(game_info: &game::GameInfo) -> String {
+fn get_enpassent(game_info: &game::Position) -> String {
     let enpassent;
 
     if let Some(square) = game_info.en_passant.last().unwrap() {
@@ -128,12 +128,12 @@ pub fn index_to_letter_pos(index: &i8) -> String {
     letter_pos
 }
 
-fn half_move_clock(game_info: &game::GameInfo) -> String {
+fn half_move_clock(game_info: &game::Position) -> String {
     let half_move = game_info.half_move_clock.last().unwrap().to_string();
 
     half_move
 }
 
-fn full_move_number(game_info: &game::GameInfo) -> String {
+fn full_move_number(game_info: &game::Position) -> String {
     game_info.full_move.to_string()
 }
@@ -1,9 +1,6 @@
-use std::sync::Arc;
-use std::sync::Mutex;
-
+use crate::bitboard::Bitboards;
 use crate::fen_reader;
-use crate::move_gen;
-use crate::move_gen::Move;
+use crate::move_gen::{self, Move};
 use crate::piece;
 
 const BLACK_KING: char = '\u{2654}';
@@ -22,8 +19,12 @@ const WHITE_PAWN: char = '\u{265F}';
 
 const EMPTY: char = '\u{25A1}';
 
-pub const TRANSPOSITION_TABLE_SIZE: u64 = u64::pow(2, 24);
-//pub const TRANSPOSITION_TABLE_SIZE: u64 = 1;
+/// Deepest ply the search keeps killer moves for.
+pub const MAX_PLY: usize = 128;
+/// Deepest iteration a search can be asked for, leaving the plies up to `MAX_PLY` to extensions
+/// and quiescence.
+pub const MAX_DEPTH: i8 = 64;
+
 #[derive(Clone, PartialEq, Eq, Debug, Copy)]
 pub enum Color {
     White,
@@ -46,9 +47,12 @@ impl Color {
     }
 }
 
+/// A position and the history to reach it, cheap to build and clone. The tables of the search
+/// belong to `engine::Engine`.
 #[derive(Clone,Debug)]
-pub struct GameInfo {
+pub struct Position {
     pub board: [piece::Piece; 120],
+    pub bitboards: Bitboards,
     pub white_pieces: piece::PieceList,
     pub black_pieces: piece::PieceList,
     pub turn: Color,
@@ -57,55 +61,44 @@ pub struct GameInfo {
     pub half_move_clock: Vec<u16>,
     pub full_move: i32,
     pub hash: u64,
-    pub transposition_table: Arc<Mutex<Vec<Eval>>>,
-    pub historic_heuristic: Arc<Mutex<[[[usize; 120]; 120]; 2]>>,
-    pub killer_move: Arc<Mutex<[[Move; 2];20]>>,
+    pub history: Vec<u64>,
 }
 
-impl GameInfo{
-    pub fn new() -> GameInfo{
+impl Position{
+    pub fn new() -> Position{
         fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
     }
+}
 
-    pub fn store_tt(&self, index: usize, flag: Flag, value: f64, depth_left: i8) {
-        let mut table = self.transposition_table.lock().unwrap();
-        if depth_left > table[index].depth{
-            table[index].flag = flag;
-            table[index].value = value;
-            table[index].depth = depth_left;
-            table[index].zobrist_key = self.hash;
-        }
+/// Queries on the side to move, answered from the bitboards without generating every move.
+impl Position {
+    pub fn in_check(&self) -> bool {
+        let king = match self.turn {
+            Color::White => self.white_pieces.kings[0],
+            Color::Black => self.black_pieces.kings[0],
+        };
+        self.bitboards.is_attacked(king, &self.turn.opposite_color())
     }
 
-}
+    /// Whether the legal `movement` checks the opponent.
+    pub fn gives_check(&self, movement: &Move) -> bool {
+        move_gen::gives_check(self, movement)
+    }
 
-#[derive(Copy, Clone, Debug)]
-pub enum Flag {
-    Exact,
-    Lowerbound,
-    Upperbound,
-}
+    pub fn is_checkmate(&self) -> bool {
+        self.in_check() && !move_gen::has_legal_move(self)
+    }
 
-#[derive(Copy, Clone, Debug)]
-pub struct Eval {
-    pub depth: i8,
-    pub zobrist_key: u64,
-    pub flag: Flag,
-    pub value: f64,
-}
+    pub fn is_stalemate(&self) -> bool {
+        !self.in_check() && !move_gen::has_legal_move(self)
+    }
 
-impl Eval {
-    pub fn new() -> Eval {
-        Eval {
-            depth: 0,
-            zobrist_key: 0,
-            flag: Flag::Exact,
-            value: 0.0,
-        }
+    pub fn legal_moves(&self) -> Vec<Move> {
+        move_gen::legal_moves(self)
     }
 }
 
-impl GameInfo {
+impl Position {
     pub fn print_board(&self) {
         for i in (0..=7).rev() {
             for j in 0..=7 {
@@ -133,15 +126,17 @@ impl GameInfo {
         println!();
     }
 
-    pub fn equal(&self, game: GameInfo) -> bool {
-        self.print_board();
-        game.print_board();
+    pub fn equal(&self, game: Position) -> bool {
         for i in 0..=119 {
             if game.board[i] != self.board[i] {
                 return false;
             }
         }
 
+        if self.bitboards != game.bitboards {
+            return false;
+        }
+
         if self.turn != game.turn {
             return false;
         }
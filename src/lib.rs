@@ -1,6 +1,8 @@
 pub mod alpha_beta_search;
 pub mod api;
 pub mod attack_gen;
+pub mod bitboard;
+pub mod engine;
 pub mod eval;
 pub mod fen_positions;
 pub mod fen_reader;
@@ -9,12 +11,18 @@ pub mod game;
 pub mod make_move;
 pub mod model;
 pub mod move_gen;
+pub mod move_picker;
 pub mod move_notation;
 pub mod notation;
 pub mod perft;
+pub mod pgn;
 pub mod piece;
+pub mod search_context;
+pub mod session;
 pub mod suite;
+pub mod time_manager;
 pub mod training_parser;
+pub mod transposition_table;
 pub mod unmake;
 pub mod zobrist_hashing;
 pub mod uci;
@@ -3,7 +3,7 @@ use crate::move_gen;
 use crate::piece;
 use crate::zobrist_hashing;
 
-pub fn make_move(game: &mut game::GameInfo, movement: &mut move_gen::Move) {
+pub fn make_move(game: &mut game::Position, movement: &mut move_gen::Move) {
     let piece: piece::Piece = game.board[movement.origin as usize];
 
     match piece {
@@ -17,10 +17,11 @@ pub fn make_move(game: &mut game::GameInfo, movement: &mut move_gen::Move) {
     }
 }
 
-fn aux(mut game: &mut game::GameInfo, movement: &mut move_gen::Move, mut piece: piece::PieceType) {
-    unsafe {
-        zobrist_hashing::HASH.hash_move(piece, &mut game.hash, movement.origin, &game.turn);
-    }
+fn aux(mut game: &mut game::Position, movement: &mut move_gen::Move, mut piece: piece::PieceType) {
+    game.history.push(game.hash);
+    let en_passant = matches!(piece, piece::PieceType::Pawn) && *game.en_passant.last().unwrap() == Some(movement.destiny);
+
+    zobrist_hashing::HASH.hash_move(piece, &mut game.hash, movement.origin, &game.turn);
 
     if let Some(piece_type) = movement.promotion {
         piece = piece_type;
@@ -39,9 +40,7 @@ fn aux(mut game: &mut game::GameInfo, movement: &mut move_gen::Move, mut piece:
         }
     }
 
-    unsafe {
-        zobrist_hashing::HASH.hash_move(piece, &mut game.hash, movement.destiny, &game.turn);
-    }
+    zobrist_hashing::HASH.hash_move(piece, &mut game.hash, movement.destiny, &game.turn);
 
     let mut square_diference: i8 = 10;
 
@@ -68,68 +67,64 @@ fn aux(mut game: &mut game::GameInfo, movement: &mut move_gen::Move, mut piece:
                     game.black_pieces
                         .remove(&piece::PieceType::Pawn, *pos - square_diference);
 
-                    unsafe {
-                        zobrist_hashing::HASH.hash_move(
-                            piece::PieceType::Pawn,
-                            &mut game.hash,
-                            *pos - square_diference,
-                            &game::Color::Black,
-                        );
-                    }
+                    zobrist_hashing::HASH.hash_move(
+                        piece::PieceType::Pawn,
+                        &mut game.hash,
+                        *pos - square_diference,
+                        &game::Color::Black,
+                    );
                 }
                 game::Color::Black => {
                     game.white_pieces
                         .remove(&piece::PieceType::Pawn, *pos - square_diference);
 
-                    unsafe {
-                        zobrist_hashing::HASH.hash_move(
-                            piece::PieceType::Pawn,
-                            &mut game.hash,
-                            *pos - square_diference,
-                            &game::Color::White,
-                        );
-                    }
+                    zobrist_hashing::HASH.hash_move(
+                        piece::PieceType::Pawn,
+                        &mut game.hash,
+                        *pos - square_diference,
+                        &game::Color::White,
+                    );
                 }
             }
         } else {
             match movement.destiny_piece {
-                piece::Piece::White(p) => unsafe {
+                piece::Piece::White(p) => {
                     zobrist_hashing::HASH.hash_move(
                         p,
                         &mut game.hash,
                         movement.destiny,
                         &game::Color::White,
                     )
-                },
-                piece::Piece::Black(p) => unsafe {
+                }
+                piece::Piece::Black(p) => {
                     zobrist_hashing::HASH.hash_move(
                         p,
                         &mut game.hash,
                         movement.destiny,
                         &game::Color::Black,
                     )
-                },
+                }
                 _ => (),
             }
         }
     } else {
         match movement.destiny_piece {
-            piece::Piece::White(p) => unsafe {
+            piece::Piece::White(p) => {
                 zobrist_hashing::HASH.hash_move(
                     p,
                     &mut game.hash,
                     movement.destiny,
                     &game::Color::White,
                 )
-            },
-            piece::Piece::Black(p) => unsafe {
+            }
+            piece::Piece::Black(p) => {
                 zobrist_hashing::HASH.hash_move(
                     p,
                     &mut game.hash,
                     movement.destiny,
                     &game::Color::Black,
                 )
-            },
+            }
             _ => (),
         }
     }
@@ -143,39 +138,35 @@ fn aux(mut game: &mut game::GameInfo, movement: &mut move_gen::Move, mut piece:
                         game.board[28] = piece::Piece::Empty;
                         game.white_pieces.make_move(&piece::PieceType::Rook, 26, 28);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                28,
-                                &game::Color::White,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                26,
-                                &game::Color::White,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            28,
+                            &game::Color::White,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            26,
+                            &game::Color::White,
+                        );
                     } else {
                         game.board[24] = game.board[21];
                         game.board[21] = piece::Piece::Empty;
                         game.white_pieces.make_move(&piece::PieceType::Rook, 24, 21);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                21,
-                                &game::Color::White,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                24,
-                                &game::Color::White,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            21,
+                            &game::Color::White,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            24,
+                            &game::Color::White,
+                        );
                     }
                 }
                 game::Color::Black => {
@@ -184,39 +175,35 @@ fn aux(mut game: &mut game::GameInfo, movement: &mut move_gen::Move, mut piece:
                         game.board[98] = piece::Piece::Empty;
                         game.black_pieces.make_move(&piece::PieceType::Rook, 96, 98);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                98,
-                                &game::Color::Black,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                96,
-                                &game::Color::Black,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            98,
+                            &game::Color::Black,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            96,
+                            &game::Color::Black,
+                        );
                     } else {
                         game.board[94] = game.board[91];
                         game.board[91] = piece::Piece::Empty;
                         game.black_pieces.make_move(&piece::PieceType::Rook, 94, 91);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                91,
-                                &game::Color::Black,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                94,
-                                &game::Color::Black,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            91,
+                            &game::Color::Black,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            94,
+                            &game::Color::Black,
+                        );
                     }
                 }
             }
@@ -224,12 +211,13 @@ fn aux(mut game: &mut game::GameInfo, movement: &mut move_gen::Move, mut piece:
     }
 
     game.board[movement.origin as usize] = piece::Piece::Empty;
+    game.bitboards.toggle_move(movement, game.board[movement.destiny as usize], en_passant);
 
     update_game_state(game, piece, movement);
 }
 
 fn update_game_state(
-    mut game: &mut game::GameInfo,
+    mut game: &mut game::Position,
     origin_piece: piece::PieceType,
     movement: &move_gen::Move,
 ) {
@@ -239,10 +227,10 @@ fn update_game_state(
         piece::Piece::White(piece) => match piece {
             piece::PieceType::Rook => {
                 if movement.destiny == 28 && castling[0] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 0) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 0);
                     castling[0] = false;
                 } else if movement.destiny == 21 && castling[1] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 1) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 1);
                     castling[1] = false;
                 }
             }
@@ -251,10 +239,10 @@ fn update_game_state(
         piece::Piece::Black(piece) => match piece {
             piece::PieceType::Rook => {
                 if movement.destiny == 98 && castling[2] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 2) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 2);
                     castling[2] = false;
                 } else if movement.destiny == 91 && castling[3] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 3) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 3);
                     castling[3] = false;
                 }
             }
@@ -268,19 +256,19 @@ fn update_game_state(
         piece::PieceType::Rook => match game.turn {
             game::Color::White => {
                 if movement.origin == 21 && castling[1] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 1) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 1);
                     castling[1] = false;
                 } else if movement.origin == 28 && castling[0] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 0) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 0);
                     castling[0] = false;
                 }
             }
             game::Color::Black => {
                 if movement.origin == 91 && castling[3] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 3) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 3);
                     castling[3] = false;
                 } else if movement.origin == 98 && castling[2] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 2) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 2);
                     castling[2] = false;
                 }
             }
@@ -288,21 +276,21 @@ fn update_game_state(
         piece::PieceType::King => match game.turn {
             game::Color::White => {
                 if castling[0] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 0) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 0);
                     castling[0] = false;
                 }
                 if castling[1] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 1) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 1);
                     castling[1] = false;
                 }
             }
             game::Color::Black => {
                 if castling[2] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 2) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 2);
                     castling[2] = false;
                 }
                 if castling[3] {
-                    unsafe { zobrist_hashing::HASH.hash_castling(&mut game.hash, 3) }
+                    zobrist_hashing::HASH.hash_castling(&mut game.hash, 3);
                     castling[3] = false;
                 }
             }
@@ -322,10 +310,7 @@ fn update_game_state(
         game.en_passant
             .push(Some(movement.destiny - square_difference));
 
-        unsafe {
-            zobrist_hashing::HASH
-                .hash_en_passant(&mut game.hash, movement.destiny - square_difference)
-        };
+        zobrist_hashing::HASH.hash_en_passant(&mut game.hash, movement.destiny - square_difference);
     } else {
         game.en_passant.push(None);
     }
@@ -352,5 +337,25 @@ fn update_game_state(
     }
 
     game.turn = game.turn.change_turn();
-    unsafe { zobrist_hashing::HASH.hash_turn(&mut game.hash) }
+    zobrist_hashing::HASH.hash_turn(&mut game.hash);
+}
+
+/// Passes the turn without moving, used by null move pruning. The half move clock restarts so
+/// no repetition is detected across the null move.
+pub fn make_null_move(game: &mut game::Position) {
+    game.history.push(game.hash);
+
+    if let Some(pos) = game.en_passant.last().unwrap() {
+        zobrist_hashing::HASH.hash_en_passant(&mut game.hash, *pos);
+    }
+    game.en_passant.push(None);
+    game.half_move_clock.push(0);
+    game.castling.push(*game.castling.last().unwrap());
+
+    if matches!(game.turn, game::Color::Black) {
+        game.full_move += 1;
+    }
+
+    game.turn = game.turn.change_turn();
+    zobrist_hashing::HASH.hash_turn(&mut game.hash);
 }
@@ -5,12 +5,13 @@ use std::{
     vec,
 };
 
-use crate::{api::{board120_to_board64, board64_to_board120}, alpha_beta_search::{alpha_beta_min_net, alpha_beta_min, alpha_beta_max}};
+use crate::{api::{board120_to_board64, board64_to_board120}, alpha_beta_search::{alpha_beta_min_net, alpha_beta_min, alpha_beta_max, SearchOptions}};
 use crate::attack_gen;
 use crate::{
     alpha_beta_search,
+    engine::Engine,
     fen_reader,
-    game::{self, GameInfo},
+    game::{self, Position},
     make_move, move_gen,
     piece::{PieceList},
     suite,
@@ -62,7 +63,7 @@ impl Module for Net {
     }
 }
 
-pub fn pre_proccess(game: &mut game::GameInfo) -> tch::Tensor {
+pub fn pre_proccess(game: &mut game::Position) -> tch::Tensor {
     let bitmaps =
         piece_lists_to_bitmaps(&game.white_pieces, &game.black_pieces).totype(tch::Kind::Float);
     let game_state = game_state(game).totype(tch::Kind::Float);
@@ -138,7 +139,7 @@ pub fn train() -> () {
             .write_all(format!("{} {}\n", epoch, accumulated_loss).as_bytes())
             .unwrap();
         
-            let score = suite::test_model_net(Some(&net),&mut suites, epoch, Duration::from_millis(TIME_LIMIT));
+            let score = suite::test_model_net(Some(&net),&mut suites, epoch, Duration::from_millis(TIME_LIMIT), SearchOptions::new());
             println!("Epoch: {} Score: {}", epoch, score);
 
             vs.save(format!("model_weights/10_hidden{}.pt", epoch))
@@ -153,6 +154,7 @@ fn tdl_train(games: &mut Vec<String>, net: &Net, accumulated_loss: &mut f64) ->
     let mut rng = rand::rngs::StdRng::seed_from_u64(seed.try_into().unwrap());
     let size = games.len();
     let mut loss = tch::Tensor::zeros(&[1], (tch::Kind::Float, tch::Device::Cpu)).set_requires_grad(true);
+    let mut engine = Engine::new();
     for _ in 0..N_GAMES {
 
         let mut game = &mut games[rng.gen_range(0..size)].clone();
@@ -182,7 +184,7 @@ fn tdl_train(games: &mut Vec<String>, net: &Net, accumulated_loss: &mut f64) ->
             }else{
 
                 //let (mut score, mut movement) = alpha_beta_search::best_move_net(DEPTH, game, net);
-                let mut movement = alpha_beta_search::iterative_deepening_time_limit_net(game, DEPTH, Duration::from_millis(100000), net).unwrap();
+                let mut movement = alpha_beta_search::iterative_deepening_time_limit_net(game, &mut engine, DEPTH, Duration::from_millis(100000), net).best_move.unwrap();
                 let score = tch::Tensor::of_slice(&[0]);
                 make_move::make_move(game, &mut movement);
                 scores.push(score);
@@ -219,10 +221,12 @@ fn bootstraping(
     let mut rng = rand::rngs::StdRng::seed_from_u64(seed.try_into().unwrap());
     let len = games.len();
     let mut losses = Vec::with_capacity(N_GAMES.try_into().unwrap());
+    let mut engine = Engine::new();
 
     for _ in 0..N_GAMES {
         let game = &mut games[rng.gen_range(0..len)].clone();
         let game = &mut fen_reader::read_fen(game);
+        engine.new_game();
         
         let mut movements = move_gen::move_gen(game);
         let len = movements.len();
@@ -231,7 +235,7 @@ fn bootstraping(
             continue;
         }
         
-        let score = tch::Tensor::of_slice(&[alpha_beta_search::iterative_deepening_time_limit(game, DEPTH, Duration::from_millis(4000)).1]);
+        let score = tch::Tensor::of_slice(&[alpha_beta_search::iterative_deepening_time_limit(game, &mut engine, DEPTH, Duration::from_millis(4000)).score]);
         
         let prediction = net.forward_t(&pre_proccess(game), true);
 
@@ -281,7 +285,7 @@ fn piece_lists_to_bitmaps(white: &PieceList, black: &PieceList) -> tch::Tensor {
     Tensor::cat(&[pawns, knights, bishops, rooks, queens, kings], 0)
 }
 
-fn game_state(game: &GameInfo) -> tch::Tensor {
+fn game_state(game: &Position) -> tch::Tensor {
     let mut game_state: Vec<i64> = vec![0; 18];
 
     game_state[0] = game.white_pieces.pawns.len() as i64;
@@ -322,7 +326,7 @@ fn game_state(game: &GameInfo) -> tch::Tensor {
     Tensor::of_slice(&game_state)
 }
 
-fn attacks(game: &mut GameInfo) -> tch::Tensor {
+fn attacks(game: &mut Position) -> tch::Tensor {
     let mut attacks: Vec<i64> = vec![0; 128];
 
     let (white_attacks, _) = attack_gen::attack_gen(game, Some(&game::Color::White));
@@ -1,6 +1,5 @@
-use async_std::println;
-
 use crate::attack_gen;
+use crate::bitboard;
 use crate::game;
 use crate::piece;
 
@@ -48,7 +47,7 @@ impl Move {
     }
 }
 
-pub fn move_gen(game: &mut game::GameInfo) -> Vec<Move> {
+pub fn move_gen(game: &mut game::Position) -> Vec<Move> {
     let mut moves: Vec<Move> = Vec::new();
     
     let (attacks, attacker_pos) = attack_gen::attack_gen(game, None);
@@ -838,3 +837,224 @@ fn pawn_promotion(origin: i8, destiny: i8, destiny_piece: piece::Piece) -> Vec<M
 
     moves
 }
+
+/// Kinds of moves `pseudo_legal` generates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveClass {
+    /// Captures, en passant and every promotion.
+    Captures,
+    /// The rest, castling included.
+    Quiets,
+}
+
+/// Moves of `class` made by the pieces on `origins` to `destinations`, both bitboards, without
+/// looking at pins or checks, which `is_legal` does for the moves that get searched. King moves are
+/// not limited to `destinations`.
+pub fn pseudo_legal(game: &game::Position, class: MoveClass, origins: u64, destinations: u64) -> Vec<Move> {
+    let mut moves: Vec<Move> = Vec::new();
+    let bitboards = &game.bitboards;
+    let own = |piece_type| match game.turn {
+        game::Color::White => bitboards.pieces(piece::Piece::White(piece_type)) & origins,
+        game::Color::Black => bitboards.pieces(piece::Piece::Black(piece_type)) & origins,
+    };
+    let occupied = bitboards.occupied();
+    let enemy = bitboards.color(&game.turn.opposite_color());
+    let targets = match class {
+        MoveClass::Captures => enemy,
+        MoveClass::Quiets => !occupied,
+    };
+
+    for origin in bitboard::squares(own(piece::PieceType::Knight)) {
+        push_moves(&mut moves, game, origin, bitboard::knight_attacks(origin) & targets & destinations);
+    }
+    for origin in bitboard::squares(own(piece::PieceType::Bishop) | own(piece::PieceType::Queen)) {
+        push_moves(&mut moves, game, origin, bitboard::bishop_attacks(origin, occupied) & targets & destinations);
+    }
+    for origin in bitboard::squares(own(piece::PieceType::Rook) | own(piece::PieceType::Queen)) {
+        push_moves(&mut moves, game, origin, bitboard::rook_attacks(origin, occupied) & targets & destinations);
+    }
+    for origin in bitboard::squares(own(piece::PieceType::King)) {
+        push_moves(&mut moves, game, origin, bitboard::king_attacks(origin) & targets);
+        if class == MoveClass::Quiets {
+            castling_moves(&mut moves, game, bitboard::index(origin));
+        }
+    }
+
+    let (forward, start_rank, last_rank): (i32, u64, u64) = match game.turn {
+        game::Color::White => (8, 0xFF00, 0xFF << 56),
+        game::Color::Black => (-8, 0xFF << 48, 0xFF),
+    };
+    for origin in bitboard::squares(own(piece::PieceType::Pawn)) {
+        let push = (origin as i32 + forward) as usize;
+        let push_bit = 1 << push & !occupied & destinations;
+
+        match class {
+            MoveClass::Captures => {
+                if push_bit & last_rank != 0 {
+                    moves.append(&mut pawn_promotion(bitboard::index(origin), bitboard::index(push), piece::Piece::Empty));
+                }
+                for destiny in bitboard::squares(bitboard::pawn_attacks(origin, &game.turn) & enemy & destinations) {
+                    let destiny = bitboard::index(destiny);
+                    if 1 << bitboard::square(destiny) & last_rank != 0 {
+                        moves.append(&mut pawn_promotion(bitboard::index(origin), destiny, game.board[destiny as usize]));
+                    } else {
+                        moves.push(Move {
+                            origin: bitboard::index(origin),
+                            destiny,
+                            destiny_piece: game.board[destiny as usize],
+                            promotion: None,
+                        });
+                    }
+                }
+                if let Some(en_passant) = *game.en_passant.last().unwrap() {
+                    let captured = en_passant - forward.signum() as i8 * 10;
+                    let squares = 1 << bitboard::square(en_passant) | 1 << bitboard::square(captured);
+                    if bitboard::pawn_attacks(origin, &game.turn) & 1 << bitboard::square(en_passant) != 0
+                        && squares & destinations != 0
+                    {
+                        moves.push(Move {
+                            origin: bitboard::index(origin),
+                            destiny: en_passant,
+                            destiny_piece: game.board[captured as usize],
+                            promotion: None,
+                        });
+                    }
+                }
+            }
+            MoveClass::Quiets => {
+                if push_bit != 0 && push_bit & last_rank == 0 {
+                    push_moves(&mut moves, game, origin, push_bit);
+                }
+                let double = (push as i32 + forward) as usize;
+                if 1 << origin & start_rank != 0 && (1 << push | 1 << double) & occupied == 0 {
+                    push_moves(&mut moves, game, origin, 1 << double & destinations);
+                }
+            }
+        }
+    }
+
+    moves
+}
+
+fn push_moves(moves: &mut Vec<Move>, game: &game::Position, origin: usize, destinations: u64) {
+    for destiny in bitboard::squares(destinations) {
+        let destiny = bitboard::index(destiny);
+        moves.push(Move {
+            origin: bitboard::index(origin),
+            destiny,
+            destiny_piece: game.board[destiny as usize],
+            promotion: None,
+        });
+    }
+}
+
+/// Castling is only generated when it is legal: the king is not in check and does not cross an
+/// attacked square.
+fn castling_moves(moves: &mut Vec<Move>, game: &game::Position, king: i8) {
+    let rights = game.castling.last().unwrap();
+    let (king_side, queen_side) = match game.turn {
+        game::Color::White => (rights[0], rights[1]),
+        game::Color::Black => (rights[2], rights[3]),
+    };
+    let enemy = game.turn.opposite_color();
+    if !(king_side || queen_side) || game.bitboards.is_attacked(king, &enemy) {
+        return;
+    }
+
+    let empty = |index: i8| is_empty(index, &game.board);
+    let safe = |index: i8| !game.bitboards.is_attacked(index, &enemy);
+    let castle = |destiny: i8| Move {
+        origin: king,
+        destiny,
+        destiny_piece: piece::Piece::Empty,
+        promotion: None,
+    };
+
+    if king_side && empty(king + 1) && empty(king + 2) && safe(king + 1) && safe(king + 2) {
+        moves.push(castle(king + 2));
+    }
+    if queen_side && empty(king - 1) && empty(king - 2) && empty(king - 3) && safe(king - 1) && safe(king - 2) {
+        moves.push(castle(king - 2));
+    }
+}
+
+/// Bitboards of the position after `movement`, without touching the game.
+fn bitboards_after(game: &game::Position, movement: &Move) -> bitboard::Bitboards {
+    let piece = game.board[movement.origin as usize];
+    let placed = match (movement.promotion, piece) {
+        (Some(promotion), piece::Piece::White(_)) => piece::Piece::White(promotion),
+        (Some(promotion), piece::Piece::Black(_)) => piece::Piece::Black(promotion),
+        _ => piece,
+    };
+    let en_passant = matches!(piece, piece::Piece::White(piece::PieceType::Pawn) | piece::Piece::Black(piece::PieceType::Pawn))
+        && is_empty(movement.destiny, &game.board)
+        && (movement.destiny - movement.origin) % 10 != 0;
+
+    let mut bitboards = game.bitboards;
+    bitboards.toggle_move(movement, placed, en_passant);
+    bitboards
+}
+
+/// Whether the pseudo-legal `movement` leaves the king out of check.
+pub fn is_legal(game: &game::Position, movement: &Move) -> bool {
+    let king = match game.board[movement.origin as usize] {
+        piece::Piece::White(piece::PieceType::King) | piece::Piece::Black(piece::PieceType::King) => movement.destiny,
+        _ => king(game),
+    };
+    !bitboards_after(game, movement).is_attacked(king, &game.turn.opposite_color())
+}
+
+/// Whether `movement` checks the opponent king, directly or by uncovering a slider.
+pub fn gives_check(game: &game::Position, movement: &Move) -> bool {
+    let king = match game.turn {
+        game::Color::White => game.black_pieces.kings[0],
+        game::Color::Black => game.white_pieces.kings[0],
+    };
+    bitboards_after(game, movement).is_attacked(king, &game.turn)
+}
+
+/// Every legal move, from the bitboards so the game is not borrowed mutably.
+pub fn legal_moves(game: &game::Position) -> Vec<Move> {
+    let mut moves = pseudo_legal(game, MoveClass::Captures, !0, !0);
+    moves.append(&mut pseudo_legal(game, MoveClass::Quiets, !0, !0));
+    moves.retain(|movement| is_legal(game, movement));
+    moves
+}
+
+/// Whether the side to move has a legal move, stopping at the first one.
+pub fn has_legal_move(game: &game::Position) -> bool {
+    [MoveClass::Captures, MoveClass::Quiets]
+        .into_iter()
+        .any(|class| pseudo_legal(game, class, !0, !0).iter().any(|movement| is_legal(game, movement)))
+}
+
+fn king(game: &game::Position) -> i8 {
+    match game.turn {
+        game::Color::White => game.white_pieces.kings[0],
+        game::Color::Black => game.black_pieces.kings[0],
+    }
+}
+
+/// Legal captures, en passant captures and promotions, for the quiescence search.
+pub fn captures(game: &game::Position) -> Vec<Move> {
+    let mut moves = pseudo_legal(game, MoveClass::Captures, !0, !0);
+    moves.retain(|movement| is_legal(game, movement));
+    moves
+}
+
+/// Legal moves of a side in check: king moves and, against a single checker, the moves that take
+/// it or block its line.
+pub fn evasions(game: &game::Position) -> Vec<Move> {
+    let king = king(game);
+    let checkers = game.bitboards.attackers(king, &game.turn.opposite_color(), game.bitboards.occupied());
+    let destinations = match checkers.count_ones() {
+        0 => !0,
+        1 => checkers | bitboard::between(bitboard::square(king), checkers.trailing_zeros() as usize),
+        _ => 0,
+    };
+
+    let mut moves = pseudo_legal(game, MoveClass::Captures, !0, destinations);
+    moves.append(&mut pseudo_legal(game, MoveClass::Quiets, !0, destinations));
+    moves.retain(|movement| is_legal(game, movement));
+    moves
+}
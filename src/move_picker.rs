@@ -0,0 +1,219 @@
+use crate::bitboard;
+use crate::engine::Engine;
+use crate::eval;
+use crate::game;
+use crate::move_gen::{self, Move, MoveClass};
+use crate::search_context::SearchContext;
+use crate::transposition_table::TranspositionTable;
+
+const GOOD_CAPTURE: i32 = 2000000;
+const KILLER: i32 = 1000000;
+const COUNTER_MOVE: i32 = 900000;
+const BAD_CAPTURE: i32 = -1000000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    HashMove,
+    Captures,
+    GoodCaptures,
+    Killers,
+    Quiets,
+    BadCaptures,
+    Evasions,
+    Done,
+}
+
+/// Hands out the moves of a node one at a time, best first: the hash move, captures that do not
+/// lose material by SEE, the killers and counter move, quiet moves by history and the losing
+/// captures. Every class is generated when its stage is reached, so a cutoff on the hash move
+/// generates nothing else, and legality is only checked for the moves handed out. In check all
+/// the evasions are generated at once.
+pub struct MovePicker {
+    stage: Stage,
+    pv_move: Option<Move>,
+    hash_move: Option<Move>,
+    killers: Vec<Move>,
+    moves: Vec<(i32, Move)>,
+    bad_captures: Vec<(i32, Move)>,
+    ply: i8,
+    in_check: bool,
+}
+
+impl MovePicker {
+    /// `pv_move`, the first move of the previous iteration at the root, goes before the move of
+    /// the transposition table.
+    pub fn new(pv_move: Option<Move>, ply: i8, in_check: bool) -> MovePicker {
+        MovePicker {
+            stage: Stage::HashMove,
+            pv_move,
+            hash_move: None,
+            killers: Vec::new(),
+            moves: Vec::new(),
+            bad_captures: Vec::new(),
+            ply,
+            in_check,
+        }
+    }
+
+    /// The next move, skipping at the root the moves excluded by a MultiPV search.
+    pub fn next(&mut self, game: &game::Position, engine: &Engine) -> Option<Move> {
+        loop {
+            let movement = self.next_move(game, engine)?;
+            if self.ply > 1 || !engine.context.is_excluded(&movement) {
+                return Some(movement);
+            }
+        }
+    }
+
+    fn next_move(&mut self, game: &game::Position, engine: &Engine) -> Option<Move> {
+        let context = &engine.context;
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.hash_move = self.find_hash_move(game, &engine.transposition_table);
+                    if self.in_check {
+                        self.stage = Stage::Evasions;
+                        self.moves = self.score_evasions(game, context);
+                    } else {
+                        self.stage = Stage::Captures;
+                    }
+                    if self.hash_move.is_some() {
+                        return self.hash_move;
+                    }
+                }
+                Stage::Captures => {
+                    for movement in move_gen::pseudo_legal(game, MoveClass::Captures, !0, !0) {
+                        if Some(movement) == self.hash_move {
+                            continue;
+                        }
+                        match eval::see(game, &movement) {
+                            gain if gain >= 0 => self.moves.push((gain, movement)),
+                            gain => self.bad_captures.push((gain, movement)),
+                        }
+                    }
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    if let Some(movement) = pick_legal(&mut self.moves, game) {
+                        return Some(movement);
+                    }
+                    self.stage = Stage::Killers;
+                    self.killers = self.find_killers(game, context);
+                }
+                Stage::Killers => {
+                    if !self.killers.is_empty() {
+                        return Some(self.killers.remove(0));
+                    }
+                    self.stage = Stage::Quiets;
+                    for movement in move_gen::pseudo_legal(game, MoveClass::Quiets, !0, !0) {
+                        if Some(movement) != self.hash_move && !self.killers_contain(&movement, context) {
+                            self.moves.push((context.history(&game.turn, &movement) as i32, movement));
+                        }
+                    }
+                }
+                Stage::Quiets => {
+                    if let Some(movement) = pick_legal(&mut self.moves, game) {
+                        return Some(movement);
+                    }
+                    self.stage = Stage::BadCaptures;
+                }
+                Stage::BadCaptures => {
+                    if let Some(movement) = pick_legal(&mut self.bad_captures, game) {
+                        return Some(movement);
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Evasions => {
+                    if let Some((_, movement)) = pick_best(&mut self.moves) {
+                        return Some(movement);
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// The pv move or the transposition table move when it is legal here, the table can hold a
+    /// move of another position with the same index.
+    fn find_hash_move(&self, game: &game::Position, transposition_table: &TranspositionTable) -> Option<Move> {
+        if let Some(pv_move) = self.pv_move {
+            if let Some(movement) = find_move(game, pv_move.origin, |movement| *movement == pv_move) {
+                return Some(movement);
+            }
+        }
+
+        let entry = transposition_table.probe(game.hash)?;
+        find_move(game, entry.best_move_origin()?, |movement| entry.is_best_move(movement))
+    }
+
+    /// Killers and counter move that are legal quiet moves here, without repeating the hash move.
+    fn find_killers(&self, game: &game::Position, context: &SearchContext) -> Vec<Move> {
+        let mut killers: Vec<Move> = Vec::new();
+        let candidates = context.killers(self.ply).into_iter().chain(context.counter_move(self.ply));
+
+        for candidate in candidates {
+            if candidate.origin == 0 || candidate.promotion.is_some() || Some(candidate) == self.hash_move || killers.contains(&candidate) {
+                continue;
+            }
+            if let Some(movement) = find_move(game, candidate.origin, |movement| *movement == candidate) {
+                if !eval::is_capture(&movement) {
+                    killers.push(movement);
+                }
+            }
+        }
+        killers
+    }
+
+    fn killers_contain(&self, movement: &Move, context: &SearchContext) -> bool {
+        context.is_killer(movement, self.ply) || context.counter_move(self.ply) == Some(*movement)
+    }
+
+    fn score_evasions(&self, game: &game::Position, context: &SearchContext) -> Vec<(i32, Move)> {
+        move_gen::evasions(game)
+            .into_iter()
+            .filter(|movement| Some(*movement) != self.hash_move)
+            .map(|movement| {
+                let score = match movement {
+                    _ if eval::is_capture(&movement) => match eval::see(game, &movement) {
+                        gain if gain >= 0 => GOOD_CAPTURE + gain,
+                        gain => BAD_CAPTURE + gain,
+                    },
+                    _ if context.is_killer(&movement, self.ply) => KILLER,
+                    _ if context.counter_move(self.ply) == Some(movement) => COUNTER_MOVE,
+                    _ => context.history(&game.turn, &movement) as i32,
+                };
+                (score, movement)
+            })
+            .collect()
+    }
+}
+
+/// The legal move among the ones the piece on `origin` has that `matches`.
+fn find_move(game: &game::Position, origin: i8, matches: impl Fn(&Move) -> bool) -> Option<Move> {
+    if !(21..=98).contains(&origin) || !(1..=8).contains(&(origin % 10)) {
+        return None;
+    }
+
+    let origins = 1 << bitboard::square(origin);
+    [MoveClass::Captures, MoveClass::Quiets]
+        .into_iter()
+        .flat_map(|class| move_gen::pseudo_legal(game, class, origins, !0))
+        .find(|movement| matches(movement) && move_gen::is_legal(game, movement))
+}
+
+/// Takes out the move with the highest score, a selection sort step, so the moves after a cutoff
+/// are never sorted. Ties keep the generation order.
+fn pick_best(moves: &mut Vec<(i32, Move)>) -> Option<(i32, Move)> {
+    let best = (0..moves.len()).min_by_key(|index| std::cmp::Reverse(moves[*index].0))?;
+    Some(moves.remove(best))
+}
+
+fn pick_legal(moves: &mut Vec<(i32, Move)>, game: &game::Position) -> Option<Move> {
+    while let Some((_, movement)) = pick_best(moves) {
+        if move_gen::is_legal(game, &movement) {
+            return Some(movement);
+        }
+    }
+    None
+}
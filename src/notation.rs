@@ -1,12 +1,25 @@
 use crate::fen_reader;
+use crate::fen_writer;
 use crate::game;
+use crate::make_move;
 use crate::move_gen;
 use crate::piece;
 use crate::piece::PieceType;
+use crate::unmake;
 use regex::Regex;
+use std::sync::LazyLock;
 
+/// Any move written in SAN, read loosely by `get_move`.
+static SAN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new("([RBQKPN])?([a-h])?([1-8])?([x])?([a-h])?([1-8])?([=]?)([QNRB]?)([+#]?)").unwrap()
+});
+/// A move other than castling in SAN without the check suffix, for `find_move`.
+static STRICT_SAN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([NBRQK])?([a-h])?([1-8])?x?([a-h][1-8])=?([NBRQ])?$").unwrap());
 
-pub fn get_move(notation: &str, game: &mut game::GameInfo) -> move_gen::Move {
+
+pub fn get_move(notation: &str, game: &mut game::Position) -> move_gen::Move {
+    let notation = notation.trim_end_matches(['+', '#']);
     let turn = &game.turn;
 
     if notation == "O-O" {
@@ -42,10 +55,7 @@ pub fn get_move(notation: &str, game: &mut game::GameInfo) -> move_gen::Move {
         };
     }
 
-    let re = Regex::new("([RBQKPN])?([a-h])?([1-8])?([x])?([a-h])?([1-8])?([=]?)([QNRB]?)([+#]?)")
-        .unwrap()
-        .captures(notation)
-        .unwrap();
+    let re = SAN.captures(notation).unwrap();
 
     let piece: piece::Piece = match re.get(1) {
         Some(x) => match x.as_str() {
@@ -95,12 +105,12 @@ pub fn get_move(notation: &str, game: &mut game::GameInfo) -> move_gen::Move {
         }
     };
 
-    let letter = match re.get(2) {
+    let mut letter = match re.get(2) {
         Some(x) => fen_reader::letter_to_column(x.as_str().chars().nth(0).unwrap()),
         None => 200,
     };
 
-    let number = match re.get(3) {
+    let mut number = match re.get(3) {
         Some(x) => x.as_str().parse::<u32>().unwrap() - 1,
         None => 200,
     };
@@ -134,80 +144,53 @@ pub fn get_move(notation: &str, game: &mut game::GameInfo) -> move_gen::Move {
         None => None,
     };
 
-    let mut origin;
-    let mut destiny = 0;
+    let origin;
+    let destiny;
     let mut destiny_piece = piece::Piece::Empty;
 
-    if capture {
+    // With a single square in the notation it is the destiny, with two the first one only
+    // disambiguates the origin (e.g. Nbd7, R1a3, Qh4e1 or exd5).
+    if re.get(5).is_some() {
         destiny = fen_reader::row_column_to_index(&capture_number, &capture_letter);
-        destiny_piece = game.board[destiny as usize];
-
-        if piece == piece::Piece::White(piece::PieceType::Pawn) {
-            let diference;
+    } else {
+        destiny = fen_reader::row_column_to_index(&number, &letter);
+        letter = 200;
+        number = 200;
+    }
 
-            if letter > capture_letter {
-                diference = 9;
-            } else {
-                diference = 11;
-            }
+    if capture {
+        destiny_piece = game.board[destiny];
+    }
 
-            origin = ((destiny) as i32 - diference) as u32;
-        } else if piece == piece::Piece::Black(piece::PieceType::Pawn) {
-            let diference;
+    if piece == piece::Piece::White(piece::PieceType::Pawn) {
+        if capture {
+            let diference = if letter > capture_letter { 9 } else { 11 };
+            origin = (destiny as i32 - diference) as u32;
 
-            if letter > capture_letter {
-                diference = -11;
-            } else {
-                diference = -9;
+            if destiny_piece == piece::Piece::Empty {
+                destiny_piece = game.board[destiny - 10];
             }
-
-            origin = ((destiny) as i32 - diference) as u32;
+        } else if game.board[destiny - 10] == piece::Piece::White(piece::PieceType::Pawn) {
+            origin = (destiny - 10) as u32;
         } else {
-            let origins = get_origin(destiny, game, piece);
-
-            if origins.len() > 1 {
-                if letter != 200 {
-                    origin = get_piece_in_column(origins, letter);
-                } else {
-                    origin = get_piece_in_rank(origins, number);
-                }
-            } else {
-                origin = origins[0] as u32;
-            }
-        }
-    } else {
-        if number != 200 && letter != 200 {
-            destiny = fen_reader::row_column_to_index(&number, &letter)
+            origin = (destiny - 20) as u32;
         }
+    } else if piece == piece::Piece::Black(piece::PieceType::Pawn) {
+        if capture {
+            let diference = if letter > capture_letter { -11 } else { -9 };
+            origin = (destiny as i32 - diference) as u32;
 
-        if piece == piece::Piece::White(piece::PieceType::Pawn) {
-            if game.board[destiny - 10 as usize] == piece::Piece::White(piece::PieceType::Pawn) {
-                origin = (destiny - 10) as u32;
-            } else {
-                origin = (destiny - 20) as u32;
-            }
-        } else if piece == piece::Piece::Black(piece::PieceType::Pawn) {
-            if game.board[destiny + 10 as usize] == piece::Piece::Black(piece::PieceType::Pawn) {
-                origin = (destiny + 10) as u32;
-            } else {
-                origin = (destiny + 20) as u32;
+            if destiny_piece == piece::Piece::Empty {
+                destiny_piece = game.board[destiny + 10];
             }
+        } else if game.board[destiny + 10] == piece::Piece::Black(piece::PieceType::Pawn) {
+            origin = (destiny + 10) as u32;
         } else {
-            let origins;
-            if destiny == 0 {
-                destiny = fen_reader::row_column_to_index(&capture_number, &capture_letter);
-                origins = get_origin(destiny, game, piece);
-
-                if letter != 200 {
-                    origin = get_piece_in_column(origins, letter);
-                } else {
-                    origin = get_piece_in_rank(origins, number);
-                }
-            } else {
-                origins = get_origin(destiny, game, piece);
-                origin = origins[0] as u32;
-            }
+            origin = (destiny + 20) as u32;
         }
+    } else {
+        let origins = get_origin(destiny, game, piece);
+        origin = get_piece_in_square(origins, letter, number);
     }
 
     move_gen::Move {
@@ -218,7 +201,7 @@ pub fn get_move(notation: &str, game: &mut game::GameInfo) -> move_gen::Move {
     }
 }
 
-fn get_origin(destiny: usize, game: &mut game::GameInfo, piece: piece::Piece) -> Vec<usize> {
+fn get_origin(destiny: usize, game: &mut game::Position, piece: piece::Piece) -> Vec<usize> {
     let moves = move_gen::move_gen(game);
     let mut origin = vec![];
 
@@ -231,22 +214,162 @@ fn get_origin(destiny: usize, game: &mut game::GameInfo, piece: piece::Piece) ->
     origin
 }
 
-fn get_piece_in_column(origins: Vec<usize>, column: u32) -> u32 {
+fn get_piece_in_square(origins: Vec<usize>, column: u32, rank: u32) -> u32 {
     for origin in &origins {
-        if (origin % 10 - 1) == column as usize {
+        if (column == 200 || (origin % 10 - 1) == column as usize)
+            && (rank == 200 || (origin / 10 - 2) == rank as usize)
+        {
             return *origin as u32;
         }
     }
 
-    panic!("{:?},{}", origins, column);
+    panic!("{:?},{},{}", origins, column, rank);
 }
 
-fn get_piece_in_rank(origins: Vec<usize>, rank: u32) -> u32 {
-    for origin in &origins {
-        if (origin / 10 - 2) == rank as usize {
-            return *origin as u32;
+/// The legal move written as `san`, or `None` when it is malformed, illegal or ambiguous. Unlike
+/// `get_move` it never panics, so it can read untrusted input.
+pub fn find_move(san: &str, game: &mut game::Position) -> Option<move_gen::Move> {
+    let san = san
+        .trim_end_matches(['!', '?'])
+        .trim_end_matches("e.p.")
+        .trim_end_matches(['+', '#']);
+    let moves = move_gen::move_gen(game);
+
+    // get_move trusts its input and panics when the move does not exist, so first make sure
+    // exactly one legal move fits the notation.
+    let candidates = moves.iter().filter(|x| fits(san, x, game)).count();
+    if candidates != 1 {
+        return None;
+    }
+    let movement = get_move(san, game);
+
+    moves.into_iter().find(|x| {
+        x.origin == movement.origin
+            && x.destiny == movement.destiny
+            && x.promotion == movement.promotion
+    })
+}
+
+fn fits(san: &str, movement: &move_gen::Move, game: &game::Position) -> bool {
+    let piece_type = match game.board[movement.origin as usize] {
+        piece::Piece::White(p) => p,
+        piece::Piece::Black(p) => p,
+        _ => return false,
+    };
+    let castling = piece_type == PieceType::King && (movement.origin - movement.destiny).abs() == 2;
+
+    match san {
+        "O-O" => return castling && movement.destiny > movement.origin,
+        "O-O-O" => return castling && movement.destiny < movement.origin,
+        _ if castling => return false,
+        _ => (),
+    }
+
+    let captures = match STRICT_SAN.captures(san) {
+        Some(captures) => captures,
+        None => return false,
+    };
+
+    let letter = match piece_type {
+        PieceType::Pawn => None,
+        p => Some(fen_writer::piece_to_letter(String::new(), &p, true)),
+    };
+    let origin = fen_writer::index_to_letter_pos(&movement.origin);
+    let promotion = movement
+        .promotion
+        .map(|p| fen_writer::piece_to_letter(String::new(), &p, true));
+
+    captures.get(1).map(|x| x.as_str().to_string()) == letter
+        && captures.get(2).is_none_or(|x| x.as_str() == &origin[0..1])
+        && captures.get(3).is_none_or(|x| x.as_str() == &origin[1..2])
+        && captures[4] == fen_writer::index_to_letter_pos(&movement.destiny)
+        && captures.get(5).map(|x| x.as_str().to_string()) == promotion
+}
+
+/// The SAN of every move of `moves`, played one after the other from `game`.
+pub fn san_line(moves: &[move_gen::Move], game: &game::Position) -> Vec<String> {
+    let mut game = game.clone();
+
+    moves
+        .iter()
+        .map(|movement| {
+            let san = get_san(movement, &mut game);
+            make_move::make_move(&mut game, &mut movement.clone());
+            san
+        })
+        .collect()
+}
+
+pub fn get_san(movement: &move_gen::Move, game: &mut game::Position) -> String {
+    let piece_type = match game.board[movement.origin as usize] {
+        piece::Piece::White(p) => p,
+        piece::Piece::Black(p) => p,
+        _ => panic!("Trying to write the notation of an empty square"),
+    };
+
+    let mut notation = String::new();
+
+    if piece_type == piece::PieceType::King && (movement.origin - movement.destiny).abs() == 2 {
+        if movement.destiny > movement.origin {
+            notation.push_str("O-O");
+        } else {
+            notation.push_str("O-O-O");
+        }
+    } else {
+        let capture = movement.destiny_piece != piece::Piece::Empty;
+        let origin = fen_writer::index_to_letter_pos(&movement.origin);
+
+        if piece_type == piece::PieceType::Pawn {
+            if capture {
+                notation.push_str(&origin[0..1]);
+            }
+        } else {
+            notation = fen_writer::piece_to_letter(notation, &piece_type, true);
+            notation.push_str(&get_disambiguation(movement, game));
+        }
+
+        if capture {
+            notation.push('x');
         }
+        notation.push_str(&fen_writer::index_to_letter_pos(&movement.destiny));
+
+        if let Some(promotion) = movement.promotion {
+            notation.push('=');
+            notation = fen_writer::piece_to_letter(notation, &promotion, true);
+        }
+    }
+
+    if game.gives_check(movement) {
+        let mut movement = *movement;
+        make_move::make_move(game, &mut movement);
+        notation.push(if game.is_checkmate() { '#' } else { '+' });
+        unmake::unmake_move(game, movement);
     }
 
-    panic!("{:?},{}", origins, rank);
+    notation
+}
+
+fn get_disambiguation(movement: &move_gen::Move, game: &mut game::Position) -> String {
+    let piece = game.board[movement.origin as usize];
+    let origin = fen_writer::index_to_letter_pos(&movement.origin);
+
+    let others: Vec<i8> = move_gen::move_gen(game)
+        .iter()
+        .filter(|x| {
+            x.destiny == movement.destiny
+                && x.origin != movement.origin
+                && game.board[x.origin as usize] == piece
+        })
+        .map(|x| x.origin)
+        .collect();
+
+    if others.is_empty() {
+        String::new()
+    } else if others.iter().all(|x| x % 10 != movement.origin % 10) {
+        origin[0..1].to_string()
+    } else if others.iter().all(|x| x / 10 != movement.origin / 10) {
+        origin[1..2].to_string()
+    } else {
+        origin
+    }
 }
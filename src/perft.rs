@@ -1,6 +1,6 @@
 use crate::{game, make_move, move_gen, move_notation, unmake};
 
-pub fn perft(depth: i8, mut game: &mut game::GameInfo) -> u64 {
+pub fn perft(depth: i8, mut game: &mut game::Position) -> u64 {
     let mut nodes: u64 = 0;
     let mut temp: u64 = 0;
 
@@ -20,7 +20,7 @@ pub fn perft(depth: i8, mut game: &mut game::GameInfo) -> u64 {
     nodes
 }
 
-pub fn perft_by_nodes(depth: i8, mut game: &mut game::GameInfo) {
+pub fn perft_by_nodes(depth: i8, mut game: &mut game::Position) {
     let moves = move_gen::move_gen(&mut game);
     let mut nodes: u64 = 0;
     let mut total = 0;
@@ -0,0 +1,317 @@
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::{fen_reader, game, make_move, move_gen, notation};
+
+pub const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+const LINE_LENGTH: usize = 80;
+
+#[derive(Clone, Debug)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<move_gen::Move>,
+    pub result: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PgnError {
+    UnterminatedTag,
+    UnterminatedComment,
+    UnbalancedVariation,
+    IllegalMove { game: usize, ply: usize, san: String },
+    InvalidFen(fen_reader::FenError),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::UnterminatedTag => write!(f, "Unterminated tag pair"),
+            PgnError::UnterminatedComment => write!(f, "Unterminated comment"),
+            PgnError::UnbalancedVariation => write!(f, "Unbalanced variation parentheses"),
+            PgnError::IllegalMove { game, ply, san } => {
+                write!(f, "Illegal move {} at ply {} of game {}", san, ply, game)
+            }
+            PgnError::InvalidFen(err) => write!(f, "Invalid FEN tag: {}", err),
+        }
+    }
+}
+
+impl Error for PgnError {}
+
+impl PgnGame {
+    pub fn new() -> PgnGame {
+        PgnGame {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// The FEN tag when the game was set up from a position, the standard start otherwise.
+    pub fn start_fen(&self) -> &str {
+        self.tag("FEN").unwrap_or(START_POSITION)
+    }
+
+    /// The position the game starts from, an error when its FEN tag is malformed.
+    pub fn start_position(&self) -> Result<game::Position, PgnError> {
+        fen_reader::try_read_fen(self.start_fen()).map_err(PgnError::InvalidFen)
+    }
+
+    /// Position reached after the first `plies` moves of the game.
+    pub fn position_after(&self, plies: usize) -> Result<game::Position, PgnError> {
+        let mut game = self.start_position()?;
+
+        for movement in self.moves.iter().take(plies) {
+            make_move::make_move(&mut game, &mut movement.clone());
+        }
+
+        Ok(game)
+    }
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses every game in `text`. Comments, NAGs, annotation suffixes and variations are skipped,
+/// only the main line is kept.
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut pgn = PgnGame::new();
+    let mut position: Option<game::Position> = None;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                if position.is_some() {
+                    games.push(std::mem::take(&mut pgn));
+                    position = None;
+                }
+                let (name, value) = read_tag(&mut chars)?;
+                pgn.set_tag(&name, &value);
+            }
+            '{' => skip_comment(&mut chars)?,
+            ';' => skip_line(&mut chars),
+            '%' => skip_line(&mut chars),
+            '(' => skip_variation(&mut chars)?,
+            ')' => return Err(PgnError::UnbalancedVariation),
+            '$' => {
+                read_token(&mut chars);
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut token = c.to_string();
+                token.push_str(&read_token(&mut chars));
+
+                if RESULTS.contains(&token.as_str()) {
+                    pgn.set_tag("Result", &token);
+                    pgn.result = token;
+                    games.push(std::mem::take(&mut pgn));
+                    position = None;
+                    continue;
+                }
+
+                let san = strip_move_number(&token);
+                if san.is_empty() {
+                    continue;
+                }
+
+                let game = match &mut position {
+                    Some(game) => game,
+                    None => position.insert(pgn.start_position()?),
+                };
+                match notation::find_move(san, game) {
+                    Some(mut movement) => {
+                        make_move::make_move(game, &mut movement);
+                        pgn.moves.push(movement);
+                    }
+                    None => {
+                        return Err(PgnError::IllegalMove {
+                            game: games.len() + 1,
+                            ply: pgn.moves.len() + 1,
+                            san: san.to_string(),
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    if position.is_some() || !pgn.tags.is_empty() {
+        games.push(pgn);
+    }
+
+    Ok(games)
+}
+
+/// Writes the game in export format: the seven tag roster first, then any other tag, then the
+/// movetext wrapped at 80 columns.
+pub fn write_pgn(pgn: &PgnGame) -> Result<String, PgnError> {
+    let mut text = String::new();
+
+    for (name, default) in SEVEN_TAG_ROSTER {
+        let value = match name {
+            "Result" => pgn.result.as_str(),
+            _ => pgn.tag(name).unwrap_or(default),
+        };
+        text.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+    }
+    for (name, value) in pgn.tags.iter() {
+        if !SEVEN_TAG_ROSTER.iter().any(|(tag, _)| tag == name) {
+            text.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+        }
+    }
+    text.push('\n');
+
+    let mut game = pgn.start_position()?;
+    let mut tokens = Vec::new();
+
+    for (index, movement) in pgn.moves.iter().enumerate() {
+        match game.turn {
+            game::Color::White => tokens.push(format!("{}.", game.full_move)),
+            game::Color::Black if index == 0 => tokens.push(format!("{}...", game.full_move)),
+            game::Color::Black => (),
+        }
+        tokens.push(notation::get_san(movement, &mut game));
+        make_move::make_move(&mut game, &mut movement.clone());
+    }
+    tokens.push(pgn.result.clone());
+
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > LINE_LENGTH {
+            text.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            text.push(' ');
+            line_length += 1;
+        }
+        line_length += token.len();
+        text.push_str(&token);
+    }
+    text.push('\n');
+
+    Ok(text)
+}
+
+fn read_tag(chars: &mut Peekable<Chars>) -> Result<(String, String), PgnError> {
+    let mut name = String::new();
+    let mut value = String::new();
+
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"' && *c != ']') {
+        name.push(c);
+    }
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+    if chars.next_if_eq(&'"').is_some() {
+        loop {
+            match chars.next() {
+                Some('\\') => match chars.next() {
+                    Some(c) => value.push(c),
+                    None => return Err(PgnError::UnterminatedTag),
+                },
+                Some('"') => break,
+                Some(c) => value.push(c),
+                None => return Err(PgnError::UnterminatedTag),
+            }
+        }
+    }
+
+    loop {
+        match chars.next() {
+            Some(']') => return Ok((name, value)),
+            Some(_) => (),
+            None => return Err(PgnError::UnterminatedTag),
+        }
+    }
+}
+
+fn read_token(chars: &mut Peekable<Chars>) -> String {
+    let mut token = String::new();
+
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"[]{}();$".contains(*c)) {
+        token.push(c);
+    }
+
+    token
+}
+
+fn skip_comment(chars: &mut Peekable<Chars>) -> Result<(), PgnError> {
+    for c in chars.by_ref() {
+        if c == '}' {
+            return Ok(());
+        }
+    }
+    Err(PgnError::UnterminatedComment)
+}
+
+fn skip_line(chars: &mut Peekable<Chars>) {
+    for c in chars.by_ref() {
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+fn skip_variation(chars: &mut Peekable<Chars>) -> Result<(), PgnError> {
+    let mut depth = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            '{' => skip_comment(chars)?,
+            ';' => skip_line(chars),
+            _ => (),
+        }
+    }
+    Err(PgnError::UnbalancedVariation)
+}
+
+fn strip_move_number(token: &str) -> &str {
+    let rest = token.trim_start_matches(|c: char| c.is_ascii_digit());
+
+    if rest.len() < token.len() && rest.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,113 @@
+use crate::game;
+use crate::move_gen::Move;
+
+/// History scores are divided by this when a new search starts, so what was learnt on the previous
+/// moves still helps the ordering but fades out.
+const HISTORY_AGING: u32 = 2;
+/// Kept below the counter move and killer scores of the move ordering.
+pub const HISTORY_MAX: u32 = 50000;
+
+/// Move ordering state built while searching: two killers and the move played to reach every
+/// ply, the history of quiet moves that caused a cutoff and the quiet move that refuted each
+/// move. Every search thread owns one, inside its `Engine`. It also keeps the root moves a MultiPV
+/// search leaves out of the line being searched.
+#[derive(Clone, Debug)]
+pub struct SearchContext {
+    killers: [[Move; 2]; game::MAX_PLY],
+    moves: [Option<Move>; game::MAX_PLY],
+    history: Box<[[[u32; 120]; 120]; 2]>,
+    counter_moves: Box<[[Option<Move>; 120]; 120]>,
+    excluded_root_moves: Vec<Move>,
+}
+
+impl SearchContext {
+    pub fn new() -> SearchContext {
+        SearchContext {
+            killers: [[Move::new(); 2]; game::MAX_PLY],
+            moves: [None; game::MAX_PLY],
+            history: Box::new([[[0; 120]; 120]; 2]),
+            counter_moves: Box::new([[None; 120]; 120]),
+            excluded_root_moves: Vec::new(),
+        }
+    }
+
+    /// Forgets everything, for a new game.
+    pub fn clear(&mut self) {
+        *self = SearchContext::new();
+    }
+
+    /// Called before every search: killers belong to the previous position and are dropped, the
+    /// history is aged.
+    pub fn new_search(&mut self) {
+        self.killers = [[Move::new(); 2]; game::MAX_PLY];
+        self.moves = [None; game::MAX_PLY];
+        for score in self.history.iter_mut().flatten().flatten() {
+            *score /= HISTORY_AGING;
+        }
+    }
+
+    /// Root moves skipped until the next call, the first moves of the lines already found.
+    pub fn exclude_root_moves(&mut self, moves: Vec<Move>) {
+        self.excluded_root_moves = moves;
+    }
+
+    pub fn excludes_root_moves(&self) -> bool {
+        !self.excluded_root_moves.is_empty()
+    }
+
+    pub fn is_excluded(&self, movement: &Move) -> bool {
+        self.excluded_root_moves.contains(movement)
+    }
+
+    /// Records the move searched from `ply`, `None` for a null move.
+    pub fn set_move(&mut self, ply: i8, movement: Option<Move>) {
+        self.moves[ply as usize] = movement;
+    }
+
+    pub fn killers(&self, ply: i8) -> [Move; 2] {
+        self.killers[ply as usize]
+    }
+
+    pub fn is_killer(&self, movement: &Move, ply: i8) -> bool {
+        self.killers[ply as usize].contains(movement)
+    }
+
+    pub fn history(&self, turn: &game::Color, movement: &Move) -> u32 {
+        self.history[side(turn)][movement.origin as usize][movement.destiny as usize]
+    }
+
+    /// Quiet move that last refuted the move played to reach `ply`.
+    pub fn counter_move(&self, ply: i8) -> Option<Move> {
+        let previous = self.moves[ply as usize - 1]?;
+        self.counter_moves[previous.origin as usize][previous.destiny as usize]
+    }
+
+    /// Called when the quiet `movement` caused a cutoff at `ply` with `depth_left` plies to go.
+    pub fn update_cutoff(&mut self, turn: &game::Color, movement: Move, ply: i8, depth_left: i8) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != movement {
+            killers[1] = killers[0];
+            killers[0] = movement;
+        }
+
+        let score = &mut self.history[side(turn)][movement.origin as usize][movement.destiny as usize];
+        *score = (*score + depth_left as u32).min(HISTORY_MAX);
+
+        if let Some(previous) = self.moves[ply as usize - 1] {
+            self.counter_moves[previous.origin as usize][previous.destiny as usize] = Some(movement);
+        }
+    }
+}
+
+impl Default for SearchContext {
+    fn default() -> Self {
+        SearchContext::new()
+    }
+}
+
+fn side(turn: &game::Color) -> usize {
+    match turn {
+        game::Color::White => 0,
+        game::Color::Black => 1,
+    }
+}
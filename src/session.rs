@@ -0,0 +1,85 @@
+use crate::eval;
+use crate::fen_reader::{self, FenError};
+use crate::fen_writer;
+use crate::game::{self, Position};
+use crate::make_move;
+use crate::move_gen::Move;
+use crate::notation;
+use crate::pgn::{self, PgnGame, START_POSITION};
+use crate::unmake;
+
+/// A game played move by move: the position keeps the whole history, so repetitions are seen by
+/// the search and moves can be taken back.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub pgn: PgnGame,
+    pub position: Position,
+}
+
+impl Session {
+    /// Game from the standard start, or set up from `fen`.
+    pub fn new(fen: Option<&str>) -> Result<Session, FenError> {
+        let mut pgn = PgnGame::new();
+        let position = match fen {
+            Some(fen) if fen != START_POSITION => {
+                let position = fen_reader::try_read_fen(fen)?;
+                pgn.set_tag("SetUp", "1");
+                pgn.set_tag("FEN", &fen_writer::write_fen(&position));
+                position
+            }
+            _ => fen_reader::read_fen(START_POSITION),
+        };
+
+        let mut session = Session { pgn, position };
+        session.pgn.result = session.result().to_string();
+        Ok(session)
+    }
+
+    /// Plays the legal `movement`.
+    pub fn play(&mut self, mut movement: Move) {
+        make_move::make_move(&mut self.position, &mut movement);
+        self.pgn.moves.push(movement);
+        self.pgn.result = self.result().to_string();
+    }
+
+    /// Takes the last move back, `None` when there is none.
+    pub fn undo(&mut self) -> Option<Move> {
+        let movement = self.pgn.moves.pop()?;
+        unmake::unmake_move(&mut self.position, movement);
+        self.pgn.result = self.result().to_string();
+        Some(movement)
+    }
+
+    /// `1-0`, `0-1` or `1/2-1/2` once the game is over by mate, stalemate, threefold repetition,
+    /// the fifty-move rule or insufficient material, `*` while it goes on.
+    pub fn result(&self) -> &'static str {
+        if self.position.is_checkmate() {
+            return match self.position.turn {
+                game::Color::White => "0-1",
+                game::Color::Black => "1-0",
+            };
+        }
+        if self.position.is_stalemate() || eval::is_draw(&self.position, 3) {
+            return "1/2-1/2";
+        }
+        "*"
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.pgn.result != "*"
+    }
+
+    pub fn fen(&self) -> String {
+        fen_writer::write_fen(&self.position)
+    }
+
+    /// The moves played so far in SAN.
+    pub fn san_moves(&self) -> Vec<String> {
+        notation::san_line(&self.pgn.moves, &fen_reader::read_fen(self.pgn.start_fen()))
+    }
+
+    /// The FEN tag is only ever written from a position that was read, so it always parses.
+    pub fn to_pgn(&self) -> String {
+        pgn::write_pgn(&self.pgn).expect("the FEN tag of a session is valid")
+    }
+}
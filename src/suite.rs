@@ -7,12 +7,16 @@ use regex;
 use tch::nn::{self};
 
 use crate::{
-    eval, fen_reader, game::{self, GameInfo}, make_move, model,
+    eval, fen_reader, game::{self, Position}, make_move, model,
     move_gen::{self, move_gen},
-    notation, unmake, alpha_beta_search::{self, iterative_deepening_time_limit_net}, uci
+    notation, unmake, alpha_beta_search::{self, iterative_deepening_time_limit_net, SearchOptions}, uci,
+    engine::Engine,
 };
 
 const UNINITIALIZED: f64 = 10.0;
+/// Every position is searched from an empty table, a small one is cleared quickly and is large
+/// enough for the short searches of a suite.
+const SUITE_HASH_MB: usize = 16;
 
 pub fn test_model() -> () {
     let paths: Vec<String> = fs::read_dir("./model_weights/")
@@ -34,7 +38,7 @@ pub fn test_model() -> () {
         total_score = 0;
 
         for (mut gameS, result) in games.iter_mut().zip(results.iter()) {
-            let mut game = &mut fen_reader::read_fen_no_tt(&gameS);
+            let mut game = &mut fen_reader::read_fen(&gameS);
             let moves = move_gen(&mut game);
             let mut max: f64 = UNINITIALIZED;
             let mut best_move: move_gen::Move = move_gen::Move::new();
@@ -76,21 +80,25 @@ pub fn test_model() -> () {
     }
 }
 
-pub fn test_model_net(net: Option<&model::Net>, suites: &mut (Vec<String>,Vec<Vec<(move_gen::Move,i64)>>), epoch: i64, time_limit: Duration) -> i64 {
+/// Scores the engine on the suites. `options` only applies to the search without `net`, pass
+/// `SearchOptions::none()` to compare against plain principal variation search.
+pub fn test_model_net(net: Option<&model::Net>, suites: &mut (Vec<String>,Vec<Vec<(move_gen::Move,i64)>>), epoch: i64, time_limit: Duration, options: SearchOptions) -> i64 {
     let games = &mut suites.0;
     let results = &mut suites.1;
     let mut total_score: i64;
     let mut suite_results = OpenOptions::new().append(true).open("./suite10.txt").unwrap();
-    let tt = std::sync::Arc::new(std::sync::Mutex::new(vec![game::Eval::new(); (game::TRANSPOSITION_TABLE_SIZE) as usize]));
+    let mut engine = Engine::with_hash(SUITE_HASH_MB);
+    engine.options = options;
     total_score = 0;
 
     for (mut gameS, result) in games.iter_mut().zip(results.iter()) {
-        let game = &mut fen_reader::read_fen_share_tt(&gameS, tt.clone());
-        fen_reader::invalidate_tt(game);
-        let best_move = match net {
-            Some(net) => iterative_deepening_time_limit_net(game, 100, time_limit, net).unwrap(),
-            None => alpha_beta_search::iterative_deepening_time_limit(game, 100, time_limit).0.unwrap(),
+        let game = &mut fen_reader::read_fen(&gameS);
+        engine.new_game();
+        let search = match net {
+            Some(net) => iterative_deepening_time_limit_net(game, &mut engine, 100, time_limit, net),
+            None => alpha_beta_search::iterative_deepening_time_limit(game, &mut engine, 100, time_limit),
         };
+        let best_move = search.best_move.unwrap();
 
         for (movement, puntuaction) in result {
             if *movement == best_move {
@@ -129,7 +137,13 @@ pub fn get_suites() -> (Vec<String>, Vec<Vec<(move_gen::Move, i64)>>) {
                 .map(|x| x.to_string())
                 .collect::<Vec<String>>();
 
-            let mut game = fen_reader::read_fen_no_tt(line.get(0).unwrap());
+            let mut game = match fen_reader::try_read_fen(line.get(0).unwrap()) {
+                Ok(game) => game,
+                Err(err) => {
+                    println!("Skipping suite position {}: {}", line[0], err);
+                    continue;
+                }
+            };
             let mut result: Vec<(move_gen::Move, i64)> = vec![];
 
             for capture in regex.captures_iter(&line[1]) {
@@ -175,7 +189,7 @@ pub fn test_engine(path: &str, time_limit: Duration) -> i64{
         uci::put(stdin, &format!("go movetime {}\n", time_limit.as_millis()));
         thread::sleep(time_limit);
         let best_move = uci::get(stdin, stdout).unwrap().split_whitespace().collect::<Vec<&str>>()[1].to_owned();
-        let mut game = fen_reader::read_fen_no_tt(&gameS);
+        let mut game = fen_reader::read_fen(&gameS);
 
         let best_move = uci::uci_to_move(&best_move, &mut game).unwrap();
 
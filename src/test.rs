@@ -1,7 +1,7 @@
 use crate::api::board120_to_board64;
 use crate::game;
 use crate::game::Color;
-use crate::game::GameInfo;
+use crate::game::Position;
 use crate::move_gen::move_gen;
 use crate::piece;
 use crate::piece::Piece;
@@ -168,7 +168,7 @@ fn scale_phase(opening_score: i32, endgame_score: i32, phase: i32) -> i32 {
     endgame_score + diff * phase / MAX_PHASE
 }
 
-pub fn evaluate_material(game: &GameInfo) -> f64 {
+pub fn evaluate_material(game: &Position) -> f64 {
     let mut ret: i32 = 0;
 
     let wqcount = game.white_pieces.queens.len() as i32;
@@ -211,7 +211,7 @@ pub fn evaluate_material(game: &GameInfo) -> f64 {
     (1e-3 * ret as f64).tanh()
 }
 
-fn evaluate_pawns(game: &GameInfo, phase: i32) -> i32 {
+fn evaluate_pawns(game: &Position, phase: i32) -> i32 {
     let mut ret = 0;
 
     for white in &game.white_pieces.pawns {
@@ -236,7 +236,7 @@ fn evaluate_pawns(game: &GameInfo, phase: i32) -> i32 {
 }
 
 fn evaluate_knights(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -269,7 +269,7 @@ fn evaluate_knights(
 }
 
 fn evaluate_bishops(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -310,7 +310,7 @@ fn evaluate_bishops(
 }
 
 fn evaluate_rooks(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -343,7 +343,7 @@ fn evaluate_rooks(
 }
 
 fn evaluate_queens(
-    game: &GameInfo,
+    game: &Position,
     phase: i32,
     white_mobility: Vec<i32>,
     black_mobility: Vec<i32>,
@@ -375,7 +375,7 @@ fn evaluate_queens(
     ret
 }
 
-fn evaluate_kings(game: &GameInfo, phase: i32) -> i32 {
+fn evaluate_kings(game: &Position, phase: i32) -> i32 {
     let mut ret = 0;
 
     let index = board120_to_board64(game.white_pieces.kings[0]) as usize;
@@ -387,7 +387,7 @@ fn evaluate_kings(game: &GameInfo, phase: i32) -> i32 {
     ret
 }
 
-pub fn static_evaluate(game: &mut GameInfo) -> f64 {
+pub fn static_evaluate(game: &mut Position) -> f64 {
     let mut ret = 0;
 
     let wqcount = game.white_pieces.queens.len() as i32;
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use crate::alpha_beta_search::SearchResult;
+use crate::game;
+use crate::move_gen;
+
+/// Moves the remaining time is split between when the GUI does not send `movestogo`.
+pub const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// Kept aside on every move for the GUI and the process communication.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+/// The hard limit is never more than this many times the soft limit.
+const HARD_LIMIT_FACTOR: u32 = 4;
+const INSTABILITY_DECAY: f64 = 0.5;
+/// Score drop, in evaluation units, that doubles the soft limit.
+const SCORE_DROP: f64 = 200.0;
+
+/// Decides how long to think on a move. Iterations are started while the elapsed time is below
+/// the soft limit, which grows when the best move keeps changing or the score drops, and the
+/// search is aborted at the hard limit.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    soft_limit: Duration,
+    hard_limit: Duration,
+    best_move: Option<move_gen::Move>,
+    score: Option<f64>,
+    instability: f64,
+    scale: f64,
+}
+
+impl TimeManager {
+    /// Limits for a clock game with `remaining` time, `increment` per move and, when known,
+    /// the moves left until the next time control.
+    pub fn new(remaining: Duration, increment: Duration, moves_to_go: Option<u32>) -> TimeManager {
+        let available = remaining.saturating_sub(MOVE_OVERHEAD).max(Duration::from_millis(1));
+        let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+        let soft_limit = (available / moves_to_go + increment * 3 / 4).min(available);
+        let hard_limit = if moves_to_go == 1 {
+            available
+        } else {
+            (soft_limit * HARD_LIMIT_FACTOR).min(available / 2).max(soft_limit)
+        };
+
+        TimeManager::with_limits(soft_limit, hard_limit)
+    }
+
+    /// Thinks exactly `time`, as `go movetime` asks.
+    pub fn fixed(time: Duration) -> TimeManager {
+        TimeManager::with_limits(time, time)
+    }
+
+    fn with_limits(soft_limit: Duration, hard_limit: Duration) -> TimeManager {
+        TimeManager {
+            soft_limit,
+            hard_limit,
+            best_move: None,
+            score: None,
+            instability: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    pub fn soft_limit(&self) -> Duration {
+        self.soft_limit.mul_f64(self.scale).min(self.hard_limit)
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard_limit
+    }
+
+    /// Called with every finished iteration, tells whether there is time to start another one.
+    pub fn should_continue(&mut self, result: &SearchResult, turn: &game::Color, elapsed: Duration) -> bool {
+        let score = match turn {
+            game::Color::White => result.score,
+            game::Color::Black => -result.score,
+        };
+
+        self.instability *= INSTABILITY_DECAY;
+        if self.best_move.is_some() && self.best_move != result.best_move {
+            self.instability += 1.0;
+        }
+
+        let drop = match self.score {
+            Some(previous) => (to_units(previous) - to_units(score)).clamp(0.0, SCORE_DROP) / SCORE_DROP,
+            None => 0.0,
+        };
+
+        self.scale = (1.0 + 0.5 * self.instability) * (1.0 + drop);
+        self.best_move = result.best_move;
+        self.score = Some(score);
+
+        elapsed < self.soft_limit()
+    }
+}
+
+fn to_units(score: f64) -> f64 {
+    score.clamp(-0.9999, 0.9999).atanh() * 1000.0
+}
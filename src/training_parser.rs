@@ -1,9 +1,9 @@
-use std::fs::{File, OpenOptions, read_dir};
-use std::io::{BufRead, BufReader, Write};
+use std::fs::{OpenOptions, read_dir, read_to_string};
+use std::io::Write;
 
 use rand::Rng;
 
-use crate::{fen_reader, fen_writer, game, make_move, notation, move_gen, unmake, eval};
+use crate::{fen_writer, game, pgn, unmake, eval};
 
 pub fn get_training_fen(path:&str) -> (){
     
@@ -22,65 +22,52 @@ fn parse_to_fen(files: Vec<String>) {
             .open("./training_fen.txt")
             .unwrap();
 
-    let movement_number = regex::Regex::new(r"([0-9]*\.)").unwrap();
-    let movement = regex::Regex::new(
-        r"(([RBQKPN]?[1-8]?[a-h]?[x]?[a-h][1-8][=]?[RBQKPN]?)?((O-O-O)?(O-O)?))",
-    )
-    .unwrap();
-    
     let mut rng = rand::thread_rng();
-    let mut last_move:move_gen::Move = move_gen::Move::new();
 
     for path in files {
         
-        let file = File::open(path).unwrap();
-        let reader = BufReader::new(file);
-        let mut checkpoint: i64 = 0;
-
-        for line in reader.lines().skip(checkpoint.try_into().unwrap()) {
-            let mut game =
-                fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-
-            if line.as_ref().unwrap().starts_with("1.") {
-                let a = movement_number
-                    .split(line.as_ref().unwrap())
-                    .collect::<Vec<&str>>();
-                let max_turn = rng.gen_range(3..25);
+        let text = read_to_string(&path).unwrap();
+        let games = match pgn::parse_pgn(&text) {
+            Ok(games) => games,
+            Err(err) => {
+                println!("Skipping {}: {}", path, err);
+                continue;
+            }
+        };
 
-                for (index, i) in a.iter().enumerate() {
+        for pgn_game in games {
+            let max_turn: usize = rng.gen_range(3..25);
 
-                    if index > max_turn {
-                        
-                        if rng.gen_bool(0.5){
-                            unmake::unmake_move(&mut game, last_move);
-                        }
-                        
-                        if game.turn == game::Color::White{
-                            if eval::check(&mut game, game::Color::White){
-                                game.turn = game::Color::Black;
-                            }
-                        }else{
-                            if eval::check(&mut game, game::Color::Black){
-                                game.turn = game::Color::White;
-                            }
-                        }
+            if pgn_game.moves.len() <= max_turn * 2 {
+                continue;
+            }
 
-                        writer
-                            .write(fen_writer::write_fen(&game).as_bytes())
-                            .unwrap();
-                        writer.write(b"\n");
-                        break;
-                    }
+            let mut game = match pgn_game.position_after(max_turn * 2) {
+                Ok(game) => game,
+                Err(err) => {
+                    println!("Skipping a game of {}: {}", path, err);
+                    continue;
+                }
+            };
 
-                    for b in movement.captures_iter(i) {
-                        if b.get(0).unwrap().as_str() != "" {
-                            let mut m = notation::get_move(b.get(0).unwrap().as_str(), &mut game);
-                            make_move::make_move(&mut game, &mut m);
-                            last_move = m;
-                        }
-                    }
+            if rng.gen_bool(0.5){
+                unmake::unmake_move(&mut game, pgn_game.moves[max_turn * 2 - 1]);
+            }
+            
+            if game.turn == game::Color::White{
+                if eval::check(&mut game, game::Color::White){
+                    game.turn = game::Color::Black;
+                }
+            }else{
+                if eval::check(&mut game, game::Color::Black){
+                    game.turn = game::Color::White;
                 }
             }
+
+            writer
+                .write(fen_writer::write_fen(&game).as_bytes())
+                .unwrap();
+            writer.write(b"\n");
         }
     }
 }
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::move_gen;
+use crate::piece::PieceType;
+
+pub const DEFAULT_SIZE_MB: usize = 256;
+const BUCKET_SIZE: usize = 4;
+const AGE_MASK: u8 = 0x0F;
+/// Set in the data of every stored entry, an empty slot is all zeros.
+const OCCUPIED: u64 = 1 << 63;
+/// Values are kept in fixed point with 24 fractional bits: steps of 6e-8, well below the
+/// `NULL_WINDOW` of the search and the `MATE_STEP` of mate scores, and a range of ±128 that holds
+/// the ±100 of the widest search window.
+const VALUE_SCALE: f64 = (1 << 24) as f64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Flag {
+    Exact,
+    Lowerbound,
+    Upperbound,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Entry {
+    pub value: f64,
+    pub depth: i8,
+    pub flag: Flag,
+    best_move: u32,
+}
+
+impl Entry {
+    /// The captured piece is not stored, so moves are matched on origin, destiny and promotion.
+    pub fn is_best_move(&self, movement: &move_gen::Move) -> bool {
+        self.best_move != 0 && self.best_move == encode_move(movement)
+    }
+
+    /// Square the best move starts from, to look it up among the moves of that piece.
+    pub fn best_move_origin(&self) -> Option<i8> {
+        match self.best_move {
+            0 => None,
+            best_move => Some((best_move & 0x7F) as i8),
+        }
+    }
+}
+
+/// Every slot keeps its key XORed with its data, so a probe racing with a store from another
+/// thread sees a key mismatch instead of a torn entry and no locking is needed.
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    buckets: usize,
+    age: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let bucket_bytes = BUCKET_SIZE * std::mem::size_of::<Slot>();
+        let buckets = (size_mb * 1024 * 1024 / bucket_bytes).max(1);
+
+        TranspositionTable {
+            slots: (0..buckets * BUCKET_SIZE)
+                .map(|_| Slot {
+                    key: AtomicU64::new(0),
+                    data: AtomicU64::new(0),
+                })
+                .collect(),
+            buckets,
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        for slot in self.bucket(hash) {
+            let data = slot.data.load(Ordering::Relaxed);
+
+            if data & OCCUPIED != 0 && slot.key.load(Ordering::Relaxed) ^ data == hash {
+                return Some(decode(data));
+            }
+        }
+
+        None
+    }
+
+    /// Overwrites the entry of the same position when the new one is at least as deep, exact or
+    /// from a newer search, otherwise replaces the shallowest and oldest entry of the bucket.
+    pub fn store(&self, hash: u64, value: f64, depth: i8, flag: Flag, best_move: Option<&move_gen::Move>) {
+        let age = self.age.load(Ordering::Relaxed);
+        let mut best_move = best_move.map_or(0, encode_move);
+        let mut replace: Option<&Slot> = None;
+        let mut replace_score = i32::MAX;
+
+        for slot in self.bucket(hash) {
+            let data = slot.data.load(Ordering::Relaxed);
+
+            if data & OCCUPIED == 0 {
+                if replace_score > i32::MIN {
+                    replace = Some(slot);
+                    replace_score = i32::MIN;
+                }
+                continue;
+            }
+
+            if slot.key.load(Ordering::Relaxed) ^ data == hash {
+                let old = decode(data);
+
+                if depth < old.depth && flag != Flag::Exact && decode_age(data) == age {
+                    return;
+                }
+                if best_move == 0 {
+                    best_move = old.best_move;
+                }
+                replace = Some(slot);
+                break;
+            }
+
+            let age_distance = (age.wrapping_sub(decode_age(data)) & AGE_MASK) as i32;
+            let score = decode(data).depth as i32 - 8 * age_distance;
+            if score < replace_score {
+                replace = Some(slot);
+                replace_score = score;
+            }
+        }
+
+        if let Some(slot) = replace {
+            let data = encode(value, depth, flag, best_move, age);
+            slot.data.store(data, Ordering::Relaxed);
+            slot.key.store(hash ^ data, Ordering::Relaxed);
+        }
+    }
+
+    /// Marks the start of a new search, entries from older searches are replaced first.
+    pub fn new_search(&self) {
+        let age = (self.age.load(Ordering::Relaxed) + 1) & AGE_MASK;
+        self.age.store(age, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.data.store(0, Ordering::Relaxed);
+            slot.key.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    /// Permille of the first thousand slots used by the current search, as UCI expects.
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = self.slots.iter().take(1000);
+        let size = sample.len();
+        let used = sample
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data & OCCUPIED != 0 && decode_age(data) == age
+            })
+            .count();
+
+        used * 1000 / size
+    }
+
+    pub fn size_mb(&self) -> usize {
+        self.slots.len() * std::mem::size_of::<Slot>() / (1024 * 1024)
+    }
+
+    fn bucket(&self, hash: u64) -> &[Slot] {
+        let index = (hash % self.buckets as u64) as usize * BUCKET_SIZE;
+        &self.slots[index..index + BUCKET_SIZE]
+    }
+}
+
+impl fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TranspositionTable({} buckets)", self.buckets)
+    }
+}
+
+// Data layout: value in fixed point in bits 0-31, best move in 32-48, depth in 49-56, flag in
+// 57-58, age in 59-62 and `OCCUPIED` in 63.
+fn encode(value: f64, depth: i8, flag: Flag, best_move: u32, age: u8) -> u64 {
+    let flag = match flag {
+        Flag::Exact => 0,
+        Flag::Lowerbound => 1,
+        Flag::Upperbound => 2,
+    };
+
+    // The cast saturates, out of range values are stored as the closest bound.
+    (value * VALUE_SCALE).round() as i32 as u32 as u64
+        | (best_move as u64) << 32
+        | (depth as u8 as u64) << 49
+        | flag << 57
+        | ((age & AGE_MASK) as u64) << 59
+        | OCCUPIED
+}
+
+fn decode(data: u64) -> Entry {
+    Entry {
+        value: data as u32 as i32 as f64 / VALUE_SCALE,
+        best_move: ((data >> 32) & 0x1FFFF) as u32,
+        depth: (data >> 49) as u8 as i8,
+        flag: match (data >> 57) & 0x3 {
+            0 => Flag::Exact,
+            1 => Flag::Lowerbound,
+            _ => Flag::Upperbound,
+        },
+    }
+}
+
+fn decode_age(data: u64) -> u8 {
+    (data >> 59) as u8 & AGE_MASK
+}
+
+fn encode_move(movement: &move_gen::Move) -> u32 {
+    let promotion = match movement.promotion {
+        None => 0,
+        Some(PieceType::Knight) => 1,
+        Some(PieceType::Bishop) => 2,
+        Some(PieceType::Rook) => 3,
+        Some(PieceType::Queen) => 4,
+        Some(_) => 5,
+    };
+
+    movement.origin as u32 | (movement.destiny as u32) << 7 | promotion << 14
+}
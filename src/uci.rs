@@ -1,6 +1,8 @@
 use std::{process, io::{Write, Read, BufRead, BufReader}, time::Duration, thread};
 
-use crate::{move_gen, fen_writer, piece, game, fen_reader, model, alpha_beta_search, eval, make_move, api};
+use serde::Serialize;
+
+use crate::{move_gen, fen_writer, piece, game, fen_reader, model, alpha_beta_search, eval, make_move, api, engine::Engine};
 
 #[derive(Debug)]
 pub enum WinSide {
@@ -25,7 +27,8 @@ pub fn play_game(
     time_limit: Duration,
 ) -> Result<WinSide, Box<dyn std::error::Error>> {
     let mut engine = get_engine(engine_path);
-    let mut game = game::GameInfo::new();
+    let mut game = game::Position::new();
+    let mut searcher = Engine::new();
     let mut moves: Vec<String> = Vec::new();
 
     let mut stdin = engine.stdin.as_mut().expect("Failed to open stdin");
@@ -38,7 +41,7 @@ pub fn play_game(
     }
 
     loop {
-        play_player_turn(stdin, &mut game, &mut moves, net.as_ref(), &time_limit)?;
+        play_player_turn(stdin, &mut game, &mut searcher, &mut moves, net.as_ref(), &time_limit)?;
 
         match eval::is_game_over(&mut game) {
             Some(value) => {
@@ -80,26 +83,22 @@ fn setup_engine(stdin: &mut std::process::ChildStdin, stdout: &mut std::process:
     Ok(())
 }
 
-fn play_engine_turn(stdin: &mut std::process::ChildStdin, stdout: &mut std::process::ChildStdout,game: &mut game::GameInfo, moves: &mut Vec<String>, time_limit: &Duration) -> Result<(), Box<dyn std::error::Error>> {
+fn play_engine_turn(stdin: &mut std::process::ChildStdin, stdout: &mut std::process::ChildStdout,game: &mut game::Position, moves: &mut Vec<String>, time_limit: &Duration) -> Result<(), Box<dyn std::error::Error>> {
     put(stdin, &format!("go movetime {}\n", time_limit.as_millis()));
-    thread::sleep(*time_limit);
     let best_move = get(stdin, stdout).unwrap().split_whitespace().collect::<Vec<&str>>()[1].to_owned();
     
-    moves.push(best_move.clone());
-    let movements = moves.iter().map(|x| x.to_owned()).collect::<Vec<String>>().join(" ");
-    
-    put(stdin, &format!("position startpos moves {}\n", movements));
-    put(stdin, "d\n");
-    let fen = get(stdin, stdout).unwrap(). replace("Fen: ", "");
-    fen_reader::read_fen_keep_transposition_table(&fen, game);
+    // Played on our own position, so its history keeps every repetition.
+    let mut movement = uci_to_move(&best_move, game)?;
+    make_move::make_move(game, &mut movement);
+    moves.push(best_move);
 
     Ok(())
 }
 
-fn play_player_turn(stdin: &mut std::process::ChildStdin,game: &mut game::GameInfo, moves: &mut Vec<String>, net: Option<&model::Net>, time_limit: &Duration) -> Result<(), Box<dyn std::error::Error>> {
+fn play_player_turn(stdin: &mut std::process::ChildStdin,game: &mut game::Position, searcher: &mut Engine, moves: &mut Vec<String>, net: Option<&model::Net>, time_limit: &Duration) -> Result<(), Box<dyn std::error::Error>> {
     let mut best_move = match net {
-        Some(net) => alpha_beta_search::iterative_deepening_time_limit_net(game, 100, *time_limit, net).unwrap(),
-        None => alpha_beta_search::iterative_deepening_time_limit(game, 100, *time_limit).0.unwrap(),
+        Some(net) => alpha_beta_search::iterative_deepening_time_limit_net(game, searcher, 100, *time_limit, net).best_move.unwrap(),
+        None => alpha_beta_search::iterative_deepening_time_limit(game, searcher, 100, *time_limit).best_move.unwrap(),
     };
     make_move::make_move(game, &mut best_move);
     let uci = move_to_uci(best_move);
@@ -133,7 +132,7 @@ pub fn put<'a>(mut output: &'a  process::ChildStdin, command: &'a str) -> (){
     thread::sleep(Duration::from_millis(25));
 }
 
-fn move_to_uci(movement: move_gen::Move) -> String{
+pub fn move_to_uci(movement: move_gen::Move) -> String{
     
     let mut uci = String::new();
 
@@ -156,18 +155,73 @@ fn move_to_uci(movement: move_gen::Move) -> String{
     uci
 }
 
-pub fn uci_to_move(movement: &str, game: &mut game::GameInfo) -> Result<move_gen::Move, Box<dyn std::error::Error>>{
+pub fn uci_to_move(movement: &str, game: &mut game::Position) -> Result<move_gen::Move, Box<dyn std::error::Error>>{
+    let (origin, destiny, promotion) = parse_uci(movement).ok_or("Invalid movement")?;
 
-    let origin_letter = fen_reader::letter_to_column(movement.chars().nth(0).unwrap());
-    let origin:i8 = (fen_reader::row_column_to_index(&movement.chars().nth(1).unwrap().to_digit(10).unwrap(), &origin_letter) -10).try_into().unwrap();
-    
-    let destiny_letter = fen_reader::letter_to_column(movement.chars().nth(2).unwrap());
-    let destiny:i8 = (fen_reader::row_column_to_index(&movement.chars().nth(3).unwrap().to_digit(10).unwrap(), &destiny_letter)- 10).try_into().unwrap();
-    
-    for m in move_gen::move_gen(game) {
-        if m.origin == origin && m.destiny == destiny {
+    for m in game.legal_moves() {
+        if m.origin == origin && m.destiny == destiny && (promotion.is_none() || m.promotion == promotion) {
             return Ok(m);
         }
     }
     Err("Invalid movement".into())
+}
+
+/// Origin, destiny and promotion of a move in UCI notation like `e7e8q`, without checking that it
+/// is legal. `None` when the text is not UCI.
+pub fn parse_uci(movement: &str) -> Option<(i8, i8, Option<piece::PieceType>)> {
+    if !movement.is_ascii() || !(4..=5).contains(&movement.len()) {
+        return None;
+    }
+
+    let promotion = match movement.chars().nth(4) {
+        Some('q') => Some(piece::PieceType::Queen),
+        Some('r') => Some(piece::PieceType::Rook),
+        Some('b') => Some(piece::PieceType::Bishop),
+        Some('n') => Some(piece::PieceType::Knight),
+        Some(_) => return None,
+        None => None,
+    };
+
+    Some((square_to_index(&movement[0..2])?, square_to_index(&movement[2..4])?, promotion))
+}
+
+/// Mailbox index of a square written like `e4`.
+fn square_to_index(square: &str) -> Option<i8> {
+    let mut chars = square.chars();
+    let column = chars.next().filter(|x| ('a'..='h').contains(x))?;
+    let row = chars.next().and_then(|x| x.to_digit(10)).filter(|x| (1..=8).contains(x))?;
+
+    Some(fen_reader::row_column_to_index(&(row - 1), &fen_reader::letter_to_column(column)) as i8)
+}
+
+/// A search score as UCI reports it, from the point of view of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Score {
+    Cp(i32),
+    /// Moves to mate, negative when the side to move is mated.
+    Mate(i32),
+}
+
+/// Converts a search score (white's point of view, squashed with `tanh`) into the UCI
+/// `cp` or `mate` score from the point of view of the side to move.
+pub fn score_to_uci(score: f64, turn: &game::Color) -> String {
+    match side_to_move_score(score, turn) {
+        Score::Cp(centipawns) => format!("cp {}", centipawns),
+        Score::Mate(moves) => format!("mate {}", moves),
+    }
+}
+
+pub fn side_to_move_score(score: f64, turn: &game::Color) -> Score {
+    let score = match turn {
+        game::Color::White => score,
+        game::Color::Black => -score,
+    };
+    if eval::is_mate_score(score) {
+        let moves = (eval::mate_distance(score) as i32 + 1) / 2;
+        return Score::Mate(if score > 0.0 { moves } else { -moves });
+    }
+    let centipawns = score.clamp(-0.9999, 0.9999).atanh() * 1000.0 * 100.0 / eval::MAT[0][eval::WP] as f64;
+
+    Score::Cp(centipawns.round() as i32)
 }
\ No newline at end of file
@@ -3,41 +3,34 @@ use crate::move_gen;
 use crate::piece;
 use crate::zobrist_hashing;
 
-pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
+pub fn unmake_move(mut game: &mut game::Position, movement: move_gen::Move) {
     if matches!(game.turn, game::Color::White) {
         game.full_move -= 1;
     }
 
     game.turn = game.turn.change_turn();
-    unsafe { zobrist_hashing::HASH.hash_turn(&mut game.hash) }
+    zobrist_hashing::HASH.hash_turn(&mut game.hash);
+    game.history.pop();
 
     let rights = game.castling.pop().unwrap();
     let actual_rights = game.castling.last().unwrap();
     game.half_move_clock.pop();
 
     if let Some(pos) = game.en_passant.pop().unwrap() {
-        unsafe { zobrist_hashing::HASH.hash_en_passant(&mut game.hash, pos) }
+        zobrist_hashing::HASH.hash_en_passant(&mut game.hash, pos);
     }
 
     if !rights[0] && actual_rights[0] {
-        unsafe {
-            zobrist_hashing::HASH.hash_castling(&mut game.hash, 0);
-        }
+        zobrist_hashing::HASH.hash_castling(&mut game.hash, 0);
     }
     if !rights[1] && actual_rights[1] {
-        unsafe {
-            zobrist_hashing::HASH.hash_castling(&mut game.hash, 1);
-        }
+        zobrist_hashing::HASH.hash_castling(&mut game.hash, 1);
     }
     if !rights[2] && actual_rights[2] {
-        unsafe {
-            zobrist_hashing::HASH.hash_castling(&mut game.hash, 2);
-        }
+        zobrist_hashing::HASH.hash_castling(&mut game.hash, 2);
     }
     if !rights[3] && actual_rights[3] {
-        unsafe {
-            zobrist_hashing::HASH.hash_castling(&mut game.hash, 3);
-        }
+        zobrist_hashing::HASH.hash_castling(&mut game.hash, 3);
     }
 
     let origin_piece = match game.board[movement.destiny as usize] {
@@ -49,6 +42,11 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
         }
     };
 
+    let en_passant = movement.promotion.is_none()
+        && matches!(origin_piece, piece::PieceType::Pawn)
+        && *game.en_passant.last().unwrap() == Some(movement.destiny);
+    game.bitboards.toggle_move(&movement, game.board[movement.destiny as usize], en_passant);
+
     match game.board[movement.destiny as usize] {
         piece::Piece::White(p) => {
             if let piece::PieceType::King = p {
@@ -58,39 +56,35 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
                         game.board[24] = piece::Piece::Empty;
                         game.white_pieces.make_move(&piece::PieceType::Rook, 21, 24);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                21,
-                                &game::Color::White,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                24,
-                                &game::Color::White,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            21,
+                            &game::Color::White,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            24,
+                            &game::Color::White,
+                        );
                     } else {
                         game.board[28] = game.board[26];
                         game.board[26] = piece::Piece::Empty;
                         game.white_pieces.make_move(&piece::PieceType::Rook, 28, 26);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                28,
-                                &game::Color::White,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                26,
-                                &game::Color::White,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            28,
+                            &game::Color::White,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            26,
+                            &game::Color::White,
+                        );
                     }
                 }
             }
@@ -103,39 +97,35 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
                         game.board[94] = piece::Piece::Empty;
                         game.black_pieces.make_move(&piece::PieceType::Rook, 91, 94);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                91,
-                                &game::Color::Black,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                94,
-                                &game::Color::Black,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            91,
+                            &game::Color::Black,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            94,
+                            &game::Color::Black,
+                        );
                     } else {
                         game.board[98] = game.board[96];
                         game.board[96] = piece::Piece::Empty;
                         game.black_pieces.make_move(&piece::PieceType::Rook, 98, 96);
 
-                        unsafe {
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                98,
-                                &game::Color::Black,
-                            );
-                            zobrist_hashing::HASH.hash_move(
-                                piece::PieceType::Rook,
-                                &mut game.hash,
-                                96,
-                                &game::Color::Black,
-                            );
-                        }
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            98,
+                            &game::Color::Black,
+                        );
+                        zobrist_hashing::HASH.hash_move(
+                            piece::PieceType::Rook,
+                            &mut game.hash,
+                            96,
+                            &game::Color::Black,
+                        );
                     }
                 }
             }
@@ -151,20 +141,18 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
                 game.black_pieces
                     .add_piece(&piece::PieceType::Pawn, &movement.origin);
 
-                unsafe {
-                    zobrist_hashing::HASH.hash_move(
-                        piece_type,
-                        &mut game.hash,
-                        movement.destiny,
-                        &game::Color::Black,
-                    );
-                    zobrist_hashing::HASH.hash_move(
-                        piece::PieceType::Pawn,
-                        &mut game.hash,
-                        movement.origin,
-                        &game::Color::Black,
-                    );
-                }
+                zobrist_hashing::HASH.hash_move(
+                    piece_type,
+                    &mut game.hash,
+                    movement.destiny,
+                    &game::Color::Black,
+                );
+                zobrist_hashing::HASH.hash_move(
+                    piece::PieceType::Pawn,
+                    &mut game.hash,
+                    movement.origin,
+                    &game::Color::Black,
+                );
             }
             game::Color::White => {
                 game.white_pieces.remove(&piece_type, movement.destiny);
@@ -172,37 +160,33 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
                 game.white_pieces
                     .add_piece(&piece::PieceType::Pawn, &movement.origin);
 
-                unsafe {
-                    zobrist_hashing::HASH.hash_move(
-                        piece_type,
-                        &mut game.hash,
-                        movement.destiny,
-                        &game::Color::White,
-                    );
-                    zobrist_hashing::HASH.hash_move(
-                        piece::PieceType::Pawn,
-                        &mut game.hash,
-                        movement.origin,
-                        &game::Color::White,
-                    );
-                }
+                zobrist_hashing::HASH.hash_move(
+                    piece_type,
+                    &mut game.hash,
+                    movement.destiny,
+                    &game::Color::White,
+                );
+                zobrist_hashing::HASH.hash_move(
+                    piece::PieceType::Pawn,
+                    &mut game.hash,
+                    movement.origin,
+                    &game::Color::White,
+                );
             }
         }
     } else {
-        unsafe {
-            zobrist_hashing::HASH.hash_move(
-                origin_piece,
-                &mut game.hash,
-                movement.origin,
-                &game.turn,
-            );
-            zobrist_hashing::HASH.hash_move(
-                origin_piece,
-                &mut game.hash,
-                movement.destiny,
-                &game.turn,
-            );
-        }
+        zobrist_hashing::HASH.hash_move(
+            origin_piece,
+            &mut game.hash,
+            movement.origin,
+            &game.turn,
+        );
+        zobrist_hashing::HASH.hash_move(
+            origin_piece,
+            &mut game.hash,
+            movement.destiny,
+            &game.turn,
+        );
         game.board[movement.origin as usize] = game.board[movement.destiny as usize];
     }
 
@@ -222,34 +206,32 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
                 game.board[movement.destiny as usize] = piece::Piece::Empty;
                 destiny = *pos - square_difference;
 
-                unsafe {
-                    zobrist_hashing::HASH.hash_move(
-                        piece::PieceType::Pawn,
-                        &mut game.hash,
-                        *pos - square_difference,
-                        &game.turn.opposite_color(),
-                    );
-                }
+                zobrist_hashing::HASH.hash_move(
+                    piece::PieceType::Pawn,
+                    &mut game.hash,
+                    *pos - square_difference,
+                    &game.turn.opposite_color(),
+                );
             }
             _ => {
                 game.board[movement.destiny as usize] = movement.destiny_piece;
                 match movement.destiny_piece {
-                    piece::Piece::White(p) => unsafe {
+                    piece::Piece::White(p) => {
                         zobrist_hashing::HASH.hash_move(
                             p,
                             &mut game.hash,
                             movement.destiny,
                             &game::Color::White,
                         );
-                    },
-                    piece::Piece::Black(p) => unsafe {
+                    }
+                    piece::Piece::Black(p) => {
                         zobrist_hashing::HASH.hash_move(
                             p,
                             &mut game.hash,
                             movement.destiny,
                             &game::Color::Black,
                         );
-                    },
+                    }
                     _ => (),
                 }
             }
@@ -257,22 +239,22 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
         None => {
             game.board[movement.destiny as usize] = movement.destiny_piece;
             match movement.destiny_piece {
-                piece::Piece::White(p) => unsafe {
+                piece::Piece::White(p) => {
                     zobrist_hashing::HASH.hash_move(
                         p,
                         &mut game.hash,
                         movement.destiny,
                         &game::Color::White,
                     );
-                },
-                piece::Piece::Black(p) => unsafe {
+                }
+                piece::Piece::Black(p) => {
                     zobrist_hashing::HASH.hash_move(
                         p,
                         &mut game.hash,
                         movement.destiny,
                         &game::Color::Black,
                     );
-                },
+                }
                 _ => (),
             }
         }
@@ -296,3 +278,21 @@ pub fn unmake_move(mut game: &mut game::GameInfo, movement: move_gen::Move) {
         _ => (),
     }
 }
+
+pub fn unmake_null_move(game: &mut game::Position) {
+    if matches!(game.turn, game::Color::White) {
+        game.full_move -= 1;
+    }
+
+    game.turn = game.turn.change_turn();
+    zobrist_hashing::HASH.hash_turn(&mut game.hash);
+    game.history.pop();
+
+    game.castling.pop();
+    game.half_move_clock.pop();
+    game.en_passant.pop();
+
+    if let Some(pos) = game.en_passant.last().unwrap() {
+        zobrist_hashing::HASH.hash_en_passant(&mut game.hash, *pos);
+    }
+}
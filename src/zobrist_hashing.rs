@@ -3,9 +3,12 @@ use crate::{
     game::Color,
     piece::{self, PieceType},
 };
-use rand::{self, Rng};
 
-pub static mut HASH: Hash = Hash::new();
+/// Keys are generated at compile time from a fixed seed, so a position hashes to the same value
+/// in every run and every process.
+pub static HASH: Hash = Hash::new(SEED);
+
+const SEED: u64 = 0x2545_F491_4F6C_DD1D;
 
 #[derive(Clone)]
 pub struct Hash {
@@ -17,13 +20,19 @@ pub struct Hash {
 }
 
 impl Hash {
-    pub const fn new() -> Hash {
+    pub const fn new(seed: u64) -> Hash {
+        let (state, white_pieces) = HashingNumbers::new(seed);
+        let (state, black_piece) = HashingNumbers::new(state);
+        let (state, turn) = split_mix(state);
+        let (state, castling) = keys::<4>(state);
+        let (_, en_passant) = keys::<8>(state);
+
         Hash {
-            white_pieces: HashingNumbers::new(),
-            black_piece: HashingNumbers::new(),
-            turn: 0,
-            castling: [0; 4],
-            en_passant: [0; 8],
+            white_pieces,
+            black_piece,
+            turn,
+            castling,
+            en_passant,
         }
     }
 
@@ -33,18 +42,6 @@ impl Hash {
         println!("en_passant: {:?}", self.en_passant);
     }
 
-    pub fn randomize(&mut self) {
-        self.white_pieces.randomize();
-        self.black_piece.randomize();
-        self.turn = rand::thread_rng().gen();
-        for i in 0..4 {
-            self.castling[i] = rand::thread_rng().gen();
-        }
-        for i in 0..8 {
-            self.en_passant[i] = rand::thread_rng().gen();
-        }
-    }
-
     pub fn hash_move(&self, piece: PieceType, hash: &mut u64, index: i8, color: &Color) {
         match color {
             Color::White => {
@@ -162,28 +159,25 @@ pub struct HashingNumbers {
 }
 
 impl HashingNumbers {
-    const fn new() -> HashingNumbers {
-        HashingNumbers {
-            pawns: [0; 64],
-            knights: [0; 64],
-            bishops: [0; 64],
-            rooks: [0; 64],
-            queens: [0; 64],
-            kings: [0; 64],
-        }
-    }
-
-    fn randomize(&mut self) {
-        let mut rng = rand::thread_rng();
-
-        for i in 0..64 {
-            self.pawns[i] = rng.gen();
-            self.knights[i] = rng.gen();
-            self.bishops[i] = rng.gen();
-            self.rooks[i] = rng.gen();
-            self.queens[i] = rng.gen();
-            self.kings[i] = rng.gen();
-        }
+    const fn new(state: u64) -> (u64, HashingNumbers) {
+        let (state, pawns) = keys::<64>(state);
+        let (state, knights) = keys::<64>(state);
+        let (state, bishops) = keys::<64>(state);
+        let (state, rooks) = keys::<64>(state);
+        let (state, queens) = keys::<64>(state);
+        let (state, kings) = keys::<64>(state);
+
+        (
+            state,
+            HashingNumbers {
+                pawns,
+                knights,
+                bishops,
+                rooks,
+                queens,
+                kings,
+            },
+        )
     }
 
     fn hash_move(&self, piece: PieceType, hash: &mut u64, index: i8) {
@@ -209,3 +203,27 @@ impl HashingNumbers {
         }
     }
 }
+
+/// SplitMix64, returns the next state and its output.
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    (state, z ^ (z >> 31))
+}
+
+const fn keys<const N: usize>(mut state: u64) -> (u64, [u64; N]) {
+    let mut keys = [0; N];
+    let mut i = 0;
+
+    while i < N {
+        let (next, key) = split_mix(state);
+        state = next;
+        keys[i] = key;
+        i += 1;
+    }
+
+    (state, keys)
+}
@@ -1,10 +1,12 @@
 use chess::{
     fen_positions::get_fen_positions,
-    fen_reader, make_move,
+    fen_reader, fen_writer, make_move,
     move_gen::{self},
     perft::perft,
     piece, unmake,
-    zobrist_hashing::HASH, notation::get_move, game,
+    zobrist_hashing::HASH, notation::{self, get_move, get_san}, game, eval, pgn, engine::Engine,
+    api::{self, requested_move, MoveError, MoveFen},
+    session::Session,
 };
 
 #[cfg(test)]
@@ -90,10 +92,6 @@ fn castling_king_side() {
 
 #[test]
 fn initial_position_depth_5() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game =
         fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ");
     let mut nodes = 0;
@@ -105,10 +103,6 @@ fn initial_position_depth_5() {
 #[ignore]
 #[test]
 fn initial_position_depth_6() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game =
         fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ");
     let mut nodes = 0;
@@ -122,10 +116,6 @@ fn initial_position_depth_6() {
 fn positions() {
     let positions = get_fen_positions();
 
-    unsafe {
-        HASH.randomize();
-    }
-
     for position in positions {
         println!("{}", position.fen);
         let mut game = fen_reader::read_fen(&position.fen);
@@ -146,10 +136,6 @@ fn positions() {
 
 #[test]
 fn zobrist_hashing_regular() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game =
         fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ");
 
@@ -169,10 +155,6 @@ fn zobrist_hashing_regular() {
 
 #[test]
 fn zobrist_hashing_en_passant() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game = fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
     let mut movement = move_gen::Move {
         origin: 31,
@@ -191,10 +173,6 @@ fn zobrist_hashing_en_passant() {
 
 #[test]
 fn zobrist_hashing_en_passant_capture() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game =
         fen_reader::read_fen("rnbqkbnr/ppppppp1/7p/P7/8/8/1PPPPPPP/RNBQKBNR b KQkq - 0 2");
 
@@ -222,10 +200,6 @@ fn zobrist_hashing_en_passant_capture() {
 
 #[test]
 fn zobrist_hashing_castling_king_side() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game = fen_reader::read_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1");
     let compare = game.hash;
 
@@ -253,10 +227,6 @@ fn zobrist_hashing_castling_king_side() {
 
 #[test]
 fn zobrist_hashing_castling_queen_side() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game = fen_reader::read_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1");
     let compare = game.hash;
 
@@ -284,10 +254,6 @@ fn zobrist_hashing_castling_queen_side() {
 
 #[test]
 fn zobrist_hashing_capture() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game =
         fen_reader::read_fen("rnbqkbnr/ppppppp1/P7/8/7p/8/1PPPPPPP/RNBQKBNR w KQkq - 0 4");
 
@@ -308,10 +274,6 @@ fn zobrist_hashing_capture() {
 
 #[test]
 fn zobrist_hashing_promotion() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let mut game =
         fen_reader::read_fen("rnbqkbnr/pPppppp1/8/8/8/7p/1PPPPPPP/RNBQKBNR w KQkq - 0 5");
 
@@ -332,10 +294,6 @@ fn zobrist_hashing_promotion() {
 
 #[test]
 fn zobrist_hashing() {
-    unsafe {
-        HASH.randomize();
-    }
-
     let positions = get_fen_positions();
 
     for position in positions {
@@ -355,3 +313,1053 @@ fn zobrist_hashing() {
         }
     }
 }
+
+#[test]
+fn threefold_repetition() {
+    let mut game = fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    let shuffle = [(22, 43), (92, 73), (43, 22), (73, 92)];
+
+    for _ in 0..2 {
+        assert_eq!(eval::is_game_over(&mut game), None);
+
+        for (origin, destiny) in shuffle {
+            let mut movement = move_gen::Move {
+                origin,
+                destiny,
+                destiny_piece: piece::Piece::Empty,
+                promotion: None,
+            };
+            make_move::make_move(&mut game, &mut movement);
+        }
+    }
+
+    assert!(eval::is_repetition(&game, 3));
+    assert_eq!(eval::is_game_over(&mut game), Some(0.0));
+}
+
+#[test]
+fn fifty_move_rule_and_insufficient_material() {
+    let mut game = fen_reader::read_fen("8/8/4k3/8/8/3K4/8/7R w - - 99 80");
+    assert_eq!(eval::is_game_over(&mut game), None);
+
+    let mut movement = move_gen::Move {
+        origin: 28,
+        destiny: 27,
+        destiny_piece: piece::Piece::Empty,
+        promotion: None,
+    };
+    make_move::make_move(&mut game, &mut movement);
+    assert!(eval::is_fifty_move_rule(&game));
+
+    assert!(eval::is_insufficient_material(&fen_reader::read_fen("8/8/4k3/8/8/3KN3/8/8 w - - 0 1")));
+    assert!(eval::is_insufficient_material(&fen_reader::read_fen("8/3b4/4k3/8/8/3K4/8/5B2 w - - 0 1")));
+    assert!(!eval::is_insufficient_material(&fen_reader::read_fen("8/2b5/4k3/8/8/3K4/8/5B2 w - - 0 1")));
+    assert!(!eval::is_insufficient_material(&fen_reader::read_fen("8/8/4k3/8/8/3KNN2/8/8 w - - 0 1")));
+}
+
+#[test]
+fn pgn_round_trip() {
+    let text = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[White "A"]
+[Black "B"]
+
+1. e4 {King's pawn} e5 2. Nf3 (2. f4 exf4 (2... d5) 3. Nf3) Nc6 $1 3. Bb5 a6?!
+4. Ba4 ; the Morphy defence
+Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 1/2-1/2
+
+[Event "Second"]
+[SetUp "1"]
+[FEN "4k3/1P6/8/8/8/8/8/4K2R b K - 0 40"]
+
+40... Kd7 41. b8=N+ Kc7 42. O-O *
+"#;
+
+    let games = pgn::parse_pgn(text).unwrap();
+    assert_eq!(games.len(), 2);
+    assert_eq!(games[0].tag("Event"), Some("Casual \"blitz\""));
+    assert_eq!(games[0].moves.len(), 16);
+    assert_eq!(games[0].result, "1/2-1/2");
+    assert_eq!(games[1].moves.len(), 4);
+    assert_eq!(games[1].moves[1].promotion, Some(piece::PieceType::Knight));
+    assert_eq!(games[1].result, "*");
+
+    let written = pgn::write_pgn(&games[1]).unwrap();
+    assert!(written.starts_with("[Event \"Second\"]\n[Site \"?\"]\n[Date \"????.??.??\"]"));
+    assert!(written.ends_with("\n40... Kd7 41. b8=N+ Kc7 42. O-O *\n"));
+
+    for game in games {
+        let reread = pgn::parse_pgn(&pgn::write_pgn(&game).unwrap()).unwrap();
+        assert_eq!(reread.len(), 1);
+        assert_eq!(reread[0].result, game.result);
+        for (a, b) in reread[0].moves.iter().zip(game.moves.iter()) {
+            assert_eq!((a.origin, a.destiny, a.promotion), (b.origin, b.destiny, b.promotion));
+        }
+    }
+
+    assert!(matches!(
+        pgn::parse_pgn("1. e4 e5 2. Ke3 *"),
+        Err(pgn::PgnError::IllegalMove { ply: 3, .. })
+    ));
+
+    // A malformed FEN tag is an error, not a crash.
+    assert!(matches!(
+        pgn::parse_pgn("[FEN \"4k3/8/8 w - - 0 1\"]\n\n1. Kd2 *"),
+        Err(pgn::PgnError::InvalidFen(_))
+    ));
+    let mut game = pgn::PgnGame::new();
+    game.set_tag("FEN", "not a fen");
+    assert!(matches!(game.position_after(0), Err(pgn::PgnError::InvalidFen(_))));
+    assert!(matches!(pgn::write_pgn(&game), Err(pgn::PgnError::InvalidFen(_))));
+}
+
+#[test]
+fn san_round_trip() {
+    for position in get_fen_positions() {
+        let mut game = fen_reader::read_fen(&position.fen);
+
+        for movement in move_gen::move_gen(&mut game) {
+            let mut m = movement;
+            make_move::make_move(&mut game, &mut m);
+
+            for reply in move_gen::move_gen(&mut game) {
+                let san = get_san(&reply, &mut game);
+                let parsed = get_move(&san, &mut game);
+
+                assert_eq!(
+                    (parsed.origin, parsed.destiny, parsed.promotion, parsed.destiny_piece),
+                    (reply.origin, reply.destiny, reply.promotion, reply.destiny_piece),
+                    "{} in {}",
+                    san,
+                    position.fen
+                );
+            }
+
+            unmake::unmake_move(&mut game, m);
+        }
+    }
+
+    let san = |fen: &str, origin: i8, destiny: i8| {
+        let mut game = fen_reader::read_fen(fen);
+        let movement = move_gen::move_gen(&mut game)
+            .into_iter()
+            .find(|x| x.origin == origin && x.destiny == destiny)
+            .unwrap();
+        get_san(&movement, &mut game)
+    };
+
+    assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", 25, 27), "O-O");
+    assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", 95, 93), "O-O-O");
+    assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", 65, 74), "exd6");
+    assert_eq!(san("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", 21, 24), "Rad1");
+    assert_eq!(san("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", 51, 41), "R4a3");
+    assert_eq!(san("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", 58, 25), "Qh4e1");
+    assert_eq!(san("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", 82, 92), "b8=Q+");
+    assert_eq!(san("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", 21, 91), "Ra8#");
+    assert_eq!(get_move("Qh4e1", &mut fen_reader::read_fen("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1")).origin, 58);
+}
+
+#[test]
+fn invalid_fen() {
+    use fen_reader::{try_read_fen, FenError};
+
+    assert!(try_read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").is_ok());
+    assert!(try_read_fen("r3k2r/8/8/8/8/8/8/R3K2R  b KQkq - 3 20").is_ok());
+
+    let error = |fen: &str| try_read_fen(fen).err().unwrap();
+
+    assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w"), FenError::FieldCount(2));
+    assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/RNBQKBNR w KQkq - 0 1"), FenError::RankCount(7));
+    assert_eq!(
+        error("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        FenError::RankLength { rank: 7, length: 7 }
+    );
+    assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1"), FenError::UnknownPiece('X'));
+    assert_eq!(
+        error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"),
+        FenError::InvalidTurn("x".to_string())
+    );
+    assert_eq!(
+        error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1"),
+        FenError::InvalidCastling("KQkx".to_string())
+    );
+    assert_eq!(
+        error("rnbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        FenError::InvalidCastling("KQkq".to_string())
+    );
+    assert_eq!(
+        error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1"),
+        FenError::InvalidEnPassant("e3".to_string())
+    );
+    assert_eq!(
+        error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1"),
+        FenError::InvalidClock("x".to_string())
+    );
+    assert_eq!(error("8/8/8/8/8/8/8/4K3 w - - 0 1"), FenError::MissingKing(game::Color::Black));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4KK2 w - - 0 1"), FenError::TooManyKings(game::Color::White));
+    assert_eq!(error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), FenError::OpponentInCheck);
+}
+
+#[test]
+fn zobrist_keys_are_deterministic() {
+    let mut keys = vec![HASH.turn];
+    keys.extend(HASH.castling);
+    keys.extend(HASH.en_passant);
+    for numbers in [&HASH.white_pieces, &HASH.black_piece] {
+        for squares in [numbers.pawns, numbers.knights, numbers.bishops, numbers.rooks, numbers.queens, numbers.kings] {
+            keys.extend(squares);
+        }
+    }
+    let count = keys.len();
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), count);
+    assert!(!keys.contains(&0));
+
+    let mut game = fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    assert_eq!(game.hash, 6698942717507262484);
+
+    for san in ["Nf3", "Nf6", "e4", "e5"] {
+        let mut movement = get_move(san, &mut game);
+        make_move::make_move(&mut game, &mut movement);
+    }
+    let transposed = fen_reader::read_fen("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    assert_eq!(game.hash, transposed.hash);
+}
+
+#[test]
+fn transposition_table_store_and_probe() {
+    use chess::transposition_table::{Flag, TranspositionTable};
+
+    let tt = TranspositionTable::new(1);
+    let mut game = fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    let movement = get_move("e4", &mut game);
+    let other = get_move("d4", &mut game);
+
+    assert!(tt.probe(game.hash).is_none());
+
+    tt.store(game.hash, 0.25, 4, Flag::Lowerbound, Some(&movement));
+    let entry = tt.probe(game.hash).unwrap();
+    assert_eq!((entry.value, entry.depth, entry.flag), (0.25, 4, Flag::Lowerbound));
+    assert!(entry.is_best_move(&movement));
+    assert!(!entry.is_best_move(&other));
+    assert!(tt.probe(game.hash ^ 1).is_none());
+
+    // A shallower bound from the same search does not replace a deeper one, and a store
+    // without a move keeps the known best move.
+    tt.store(game.hash, -0.5, 2, Flag::Upperbound, None);
+    assert_eq!(tt.probe(game.hash).unwrap().depth, 4);
+    tt.store(game.hash, -0.5, 6, Flag::Exact, None);
+    let entry = tt.probe(game.hash).unwrap();
+    assert_eq!((entry.value, entry.depth, entry.flag), (-0.5, 6, Flag::Exact));
+    assert!(entry.is_best_move(&movement));
+
+    // An entry whose fields are all zero is still found.
+    tt.clear();
+    tt.store(game.hash, 0.0, 0, Flag::Exact, None);
+    let entry = tt.probe(game.hash).unwrap();
+    assert_eq!((entry.value, entry.depth, entry.flag, entry.best_move_origin()), (0.0, 0, Flag::Exact, None));
+
+    // Values keep far more precision than the zero windows of the search need, mate scores keep
+    // their distance and the widest window bounds are exact.
+    let mate = eval::MATE_SCORE - 7.0 * eval::MATE_STEP;
+    for value in [0.123_456_789, -0.987_654_321, 1e-6, mate, -mate, 100.0, -100.0] {
+        tt.store(game.hash, value, 8, Flag::Exact, None);
+        let stored = tt.probe(game.hash).unwrap().value;
+        assert!((stored - value).abs() < 1e-7, "{} stored as {}", value, stored);
+    }
+    tt.store(game.hash, mate, 8, Flag::Exact, None);
+    assert_eq!(eval::mate_distance(tt.probe(game.hash).unwrap().value), 6);
+    tt.store(game.hash, -100.0, 8, Flag::Exact, None);
+    assert_eq!(tt.probe(game.hash).unwrap().value, -100.0);
+
+    for key in 0..100_000u64 {
+        tt.store(key.wrapping_mul(0x9E37_79B9_7F4A_7C15), 0.0, 1, Flag::Exact, None);
+    }
+    assert!(tt.hashfull() > 500);
+    tt.clear();
+    assert!(tt.probe(game.hash).is_none());
+    assert_eq!(tt.hashfull(), 0);
+}
+
+#[test]
+fn search_result_statistics() {
+    use chess::alpha_beta_search::iterative_deepening_time_limit_info;
+    use std::time::Duration;
+
+    let mut game = fen_reader::read_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    let mut depths = Vec::new();
+
+    let result = iterative_deepening_time_limit_info(&mut game, &mut Engine::with_hash(16), 3, Duration::from_secs(600), &mut |result| {
+        assert!(!result.pv.is_empty());
+        assert_eq!(result.best_move.map(|x| (x.origin, x.destiny)), Some((result.pv[0].origin, result.pv[0].destiny)));
+        depths.push(result.depth);
+    });
+
+    assert_eq!(depths, vec![1, 2, 3]);
+    assert_eq!(result.depth, 3);
+    assert!(result.seldepth >= result.depth);
+    assert!(result.nodes > 0 && result.nps > 0);
+    assert!(result.tt_hit_rate > 0.0 && result.tt_hit_rate <= 1.0);
+}
+
+#[test]
+fn mate_scores() {
+    use chess::alpha_beta_search::{iterative_deepening_time_limit, SearchOptions};
+    use chess::uci::score_to_uci;
+    use std::time::Duration;
+
+    // Rg7 or Rf7 and the other rook mates on the eighth rank. The selective search only stops
+    // once the next iteration confirms the mate, the full width one as soon as it is found.
+    let mut engine = Engine::with_hash(16);
+    for (options, depth) in [(SearchOptions::new(), 4), (SearchOptions::none(), 3)] {
+        engine.options = options;
+        engine.new_game();
+        let mut game = fen_reader::read_fen("1k6/8/8/8/8/8/6R1/5R1K w - - 0 1");
+        let result = iterative_deepening_time_limit(&mut game, &mut engine, 20, Duration::from_secs(600));
+
+        assert_eq!(result.depth, depth);
+        assert_eq!(score_to_uci(result.score, &game.turn), "mate 2");
+        assert_eq!(result.pv.len(), 3);
+    }
+    engine.options = SearchOptions::new();
+
+    let mut game = fen_reader::read_fen("1k6/6R1/8/8/8/8/8/5R1K b - - 1 1");
+    let result = iterative_deepening_time_limit(&mut game, &mut engine, 20, Duration::from_secs(600));
+
+    assert_eq!(score_to_uci(result.score, &game.turn), "mate -1");
+}
+
+#[test]
+fn null_move() {
+    use chess::make_move::make_null_move;
+    use chess::unmake::unmake_null_move;
+
+    let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+    let mut game = fen_reader::read_fen(fen);
+    let hash = game.hash;
+
+    make_null_move(&mut game);
+    assert_eq!(game.hash, fen_reader::read_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2").hash);
+    assert_eq!(fen_writer::write_fen(&game), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+
+    unmake_null_move(&mut game);
+    assert_eq!(game.hash, hash);
+    assert_eq!(fen_writer::write_fen(&game), fen);
+}
+
+#[test]
+fn selective_search_finds_mate() {
+    use chess::alpha_beta_search::{iterative_deepening_time_limit, SearchOptions};
+    use chess::uci::score_to_uci;
+    use std::time::Duration;
+
+    let mut nodes = Vec::new();
+    for options in [SearchOptions::new(), SearchOptions::none()] {
+        let mut game = fen_reader::read_fen("1k6/8/8/8/8/8/6R1/5R1K w - - 0 1");
+        let mut engine = Engine::with_hash(16);
+        engine.options = options;
+        let result = iterative_deepening_time_limit(&mut game, &mut engine, 20, Duration::from_secs(600));
+
+        assert_eq!(score_to_uci(result.score, &game.turn), "mate 2");
+        nodes.push(result.nodes);
+    }
+    // Pruning and reductions search fewer nodes to find the same mate.
+    assert!(nodes[0] < nodes[1], "{:?}", nodes);
+
+    let mut game = fen_reader::read_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    let nodes: Vec<u64> = [SearchOptions::new(), SearchOptions::none()]
+        .into_iter()
+        .map(|options| {
+            let mut engine = Engine::with_hash(16);
+            engine.options = options;
+            iterative_deepening_time_limit(&mut game, &mut engine, 5, Duration::from_secs(600)).nodes
+        })
+        .collect();
+    assert!(nodes[0] * 2 < nodes[1], "{:?}", nodes);
+}
+
+#[test]
+fn aspiration_windows() {
+    use chess::alpha_beta_search::iterative_deepening_time_limit_info;
+    use chess::uci::score_to_uci;
+    use std::time::Duration;
+
+    let mut game = fen_reader::read_fen("8/5k2/3p4/1p1P4/1P3K2/8/6R1/8 w - - 0 1");
+    let mut iterations = Vec::new();
+
+    let result = iterative_deepening_time_limit_info(&mut game, &mut Engine::with_hash(16), 6, Duration::from_secs(600), &mut |result| {
+        iterations.push((result.score, result.aspiration_researches));
+    });
+
+    assert_eq!(iterations.len(), 6);
+    assert!(iterations.iter().all(|(score, _)| score.abs() < 1.0));
+    assert!(iterations.windows(2).all(|x| x[0].1 <= x[1].1));
+    assert_eq!(result.score, iterations[5].0);
+    assert_eq!(result.aspiration_researches, iterations[5].1);
+
+    // The mate only shows up at depth 5: that iteration fails high on the window around the
+    // score of depth 4 and widens it until the mate fits.
+    let mut game = fen_reader::read_fen("r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1");
+    let turn = game.turn;
+    let mut iterations = Vec::new();
+
+    let result = iterative_deepening_time_limit_info(&mut game, &mut Engine::with_hash(16), 5, Duration::from_secs(600), &mut |result| {
+        iterations.push((score_to_uci(result.score, &turn), result.aspiration_researches));
+    });
+
+    assert!(iterations[3].0.starts_with("cp"));
+    assert!(iterations[4].1 > iterations[3].1);
+    assert_eq!(score_to_uci(result.score, &turn), "mate 3");
+}
+
+#[test]
+fn time_manager() {
+    use chess::alpha_beta_search::{iterative_deepening_time_manager, SearchResult};
+    use chess::time_manager::TimeManager;
+    use std::time::Duration;
+
+    let remaining = Duration::from_secs(60);
+    let time_manager = TimeManager::new(remaining, Duration::from_secs(1), None);
+    assert!(time_manager.soft_limit() < time_manager.hard_limit());
+    assert!(time_manager.hard_limit() <= remaining / 2);
+
+    let last_move = TimeManager::new(Duration::from_secs(10), Duration::ZERO, Some(1));
+    assert!(last_move.hard_limit() > Duration::from_secs(9) && last_move.hard_limit() < Duration::from_secs(10));
+
+    let fixed = TimeManager::fixed(Duration::from_millis(200));
+    assert_eq!(fixed.soft_limit(), fixed.hard_limit());
+
+    // A best move that keeps changing earns more time, up to the hard limit.
+    let mut time_manager = TimeManager::new(remaining, Duration::ZERO, None);
+    let soft_limit = time_manager.soft_limit();
+    let mut result = SearchResult::new();
+    for movement in move_gen::move_gen(&mut game::Position::new()).into_iter().take(3) {
+        result.best_move = Some(movement);
+        assert!(time_manager.should_continue(&result, &game::Color::White, Duration::ZERO));
+    }
+    assert!(time_manager.soft_limit() > soft_limit);
+    assert!(time_manager.soft_limit() <= time_manager.hard_limit());
+    assert!(!time_manager.should_continue(&result, &game::Color::White, time_manager.hard_limit()));
+
+    // Only one legal move, the king has to take the checking queen.
+    let mut game = fen_reader::read_fen("7k/8/8/8/8/8/6q1/7K w - - 0 1");
+    let mut time_manager = TimeManager::new(Duration::from_secs(600), Duration::ZERO, None);
+    let result = iterative_deepening_time_manager(&mut game, &mut Engine::with_hash(16), 20, &mut time_manager, &mut |_| ());
+    assert_eq!(result.depth, 1);
+    assert_eq!(result.best_move.map(|x| x.destiny), Some(37));
+}
+
+#[test]
+fn stop_and_ponder() {
+    use chess::alpha_beta_search::{iterative_deepening_time_limit, SearchControl};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+    // A stop from another thread ends a search that would otherwise run for ten minutes.
+    let mut game = fen_reader::read_fen(fen);
+    let mut engine = Engine::with_hash(16);
+    let control = engine.control.clone();
+    let search = thread::spawn(move || iterative_deepening_time_limit(&mut game, &mut engine, 100, Duration::from_secs(600)));
+    thread::sleep(Duration::from_millis(300));
+    let start = Instant::now();
+    control.stop();
+    let result = search.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(result.best_move.is_some());
+    assert!(result.score.abs() < 1.0);
+
+    // While pondering the time limit is ignored, after ponderhit it applies again.
+    let mut game = fen_reader::read_fen(fen);
+    let mut engine = Engine::with_hash(16);
+    let control = Arc::new(SearchControl::pondering());
+    engine.control = control.clone();
+    let search = thread::spawn(move || iterative_deepening_time_limit(&mut game, &mut engine, 100, Duration::from_millis(50)));
+    thread::sleep(Duration::from_millis(300));
+    assert!(!search.is_finished());
+    let start = Instant::now();
+    control.ponderhit();
+    let result = search.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(result.best_move.is_some());
+}
+
+#[test]
+fn lazy_smp() {
+    use chess::alpha_beta_search::{iterative_deepening_time_limit, SearchOptions};
+    use chess::uci::score_to_uci;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let options = SearchOptions { threads: 4, ..SearchOptions::new() };
+
+    let mut game = fen_reader::read_fen("1k6/8/8/8/8/8/6R1/5R1K w - - 0 1");
+    let mut engine = Engine::with_hash(16);
+    engine.options = options;
+    let result = iterative_deepening_time_limit(&mut game, &mut engine, 20, Duration::from_secs(600));
+    assert_eq!(score_to_uci(result.score, &game.turn), "mate 2");
+
+    // Stopping the search stops the helpers too, the search does not wait for them.
+    let mut game = fen_reader::read_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    let mut engine = Engine::with_hash(16);
+    engine.options = options;
+    let control = engine.control.clone();
+    let search = thread::spawn(move || iterative_deepening_time_limit(&mut game, &mut engine, 100, Duration::from_secs(600)));
+    thread::sleep(Duration::from_millis(300));
+    let start = Instant::now();
+    control.stop();
+    let result = search.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(result.best_move.is_some());
+    assert!(result.nodes > 0);
+}
+
+#[test]
+fn search_context() {
+    use chess::search_context::{SearchContext, HISTORY_MAX};
+
+    let mut game = game::Position::new();
+    let moves = move_gen::move_gen(&mut game);
+    let mut context = SearchContext::new();
+
+    // The killers, history and counter move of a cutoff two plies into the tree, the first
+    // ply is the deepest the killers of the old 20 ply table could reach.
+    context.set_move(1, Some(moves[0]));
+    context.set_move(50, Some(moves[1]));
+    context.update_cutoff(&game::Color::White, moves[2], 51, 10);
+    assert!(context.is_killer(&moves[2], 51));
+    assert!(!context.is_killer(&moves[2], 50));
+    assert_eq!(context.history(&game::Color::White, &moves[2]), 10);
+    assert_eq!(context.history(&game::Color::Black, &moves[2]), 0);
+    assert_eq!(context.counter_move(51), Some(moves[2]));
+    assert_eq!(context.counter_move(2), None);
+
+    // A new search drops the killers and ages the history.
+    context.new_search();
+    assert!(!context.is_killer(&moves[2], 51));
+    assert_eq!(context.history(&game::Color::White, &moves[2]), 5);
+
+    for _ in 0..10000 {
+        context.update_cutoff(&game::Color::White, moves[2], 1, 100);
+    }
+    assert_eq!(context.history(&game::Color::White, &moves[2]), HISTORY_MAX);
+
+    context.clear();
+    assert_eq!(context.history(&game::Color::White, &moves[2]), 0);
+}
+
+#[test]
+fn static_exchange_evaluation() {
+    use chess::uci::uci_to_move;
+
+    let pawn = eval::MAT[0][eval::WP];
+    let knight = eval::MAT[0][eval::WN];
+    let bishop = eval::MAT[0][eval::WB];
+    let rook = eval::MAT[0][eval::WR];
+
+    let see = |fen: &str, movement: &str| {
+        let mut game = fen_reader::read_fen(fen);
+        let movement = uci_to_move(movement, &mut game).unwrap();
+        eval::see(&game, &movement)
+    };
+
+    // Undefended pawn.
+    assert_eq!(see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), pawn);
+    // The queen behind the bishop and the queen behind the rook join the exchange.
+    assert_eq!(see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"), pawn - knight);
+    // Rook for a pawn defended by a pawn.
+    assert_eq!(see("4k3/8/3p4/4p3/8/8/8/4RK2 w - - 0 1", "e1e5"), pawn - rook);
+    // Equal trades.
+    assert_eq!(see("4k3/8/3p4/4n3/8/3N4/8/4K3 w - - 0 1", "d3e5"), 0);
+    assert_eq!(see("4k3/3p4/4b3/8/2B5/8/8/4K3 w - - 0 1", "c4e6"), bishop - bishop);
+    // The king cannot take back while the rook behind still attacks the square.
+    assert_eq!(see("4k3/3r4/8/8/8/8/8/3RK3 b - - 0 1", "d7d1"), 0);
+    assert_eq!(see("3rk3/3r4/8/8/8/8/8/3RK3 b - - 0 1", "d7d1"), rook);
+    // En passant.
+    assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), pawn);
+}
+
+#[test]
+fn bitboards_follow_the_board() {
+    use chess::attack_gen::attack_gen;
+    use chess::bitboard::Bitboards;
+
+    // Every node of the tree: the bitboards match the mailbox and agree with `attack_gen` on
+    // check, through castling, en passant and promotions.
+    fn walk(game: &mut game::Position, depth: i8) {
+        assert_eq!(game.bitboards, Bitboards::from_board(&game.board));
+        let (attacks, _) = attack_gen(game, None);
+        let king = match game.turn {
+            game::Color::White => game.white_pieces.kings[0],
+            game::Color::Black => game.black_pieces.kings[0],
+        };
+        assert_eq!(game.in_check(), attacks[king as usize] > 0);
+
+        if depth == 0 {
+            return;
+        }
+        for mut movement in move_gen::move_gen(game) {
+            make_move::make_move(game, &mut movement);
+            walk(game, depth - 1);
+            unmake::unmake_move(game, movement);
+        }
+    }
+
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    ] {
+        walk(&mut fen_reader::read_fen(fen), 3);
+    }
+}
+
+#[test]
+fn pseudo_legal_generation() {
+    use chess::move_gen::{captures, evasions, is_legal, pseudo_legal, Move, MoveClass};
+
+    fn key(movement: &Move) -> (i8, i8, String) {
+        (movement.origin, movement.destiny, format!("{:?}", movement.promotion))
+    }
+    fn sorted(moves: &[Move]) -> Vec<(i8, i8, String)> {
+        let mut keys: Vec<_> = moves.iter().map(key).collect();
+        keys.sort();
+        keys
+    }
+
+    // Every node of the tree: the legal pseudo-legal moves are the ones `move_gen` generates, and
+    // the captures and evasions generators return their part of them.
+    fn walk(game: &mut game::Position, depth: i8) {
+        let legal = move_gen::move_gen(game);
+        let mut pseudo = pseudo_legal(game, MoveClass::Captures, !0, !0);
+        pseudo.append(&mut pseudo_legal(game, MoveClass::Quiets, !0, !0));
+        pseudo.retain(|movement| is_legal(game, movement));
+        assert_eq!(sorted(&pseudo), sorted(&legal), "{}", fen_writer::write_fen(game));
+
+        let capturing: Vec<Move> = legal.iter().filter(|x| eval::is_capture(x)).copied().collect();
+        assert_eq!(sorted(&captures(game)), sorted(&capturing));
+        if game.in_check() {
+            assert_eq!(sorted(&evasions(game)), sorted(&legal));
+        }
+
+        if depth == 0 {
+            return;
+        }
+        for mut movement in legal {
+            make_move::make_move(game, &mut movement);
+            walk(game, depth - 1);
+            unmake::unmake_move(game, movement);
+        }
+    }
+
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    ] {
+        walk(&mut fen_reader::read_fen(fen), 3);
+    }
+}
+
+#[test]
+fn check_queries() {
+    // Every move of the tree: `gives_check` agrees with playing the move.
+    fn walk(game: &mut game::Position, depth: i8) {
+        for mut movement in game.legal_moves() {
+            let gives_check = game.gives_check(&movement);
+            make_move::make_move(game, &mut movement);
+            assert_eq!(gives_check, game.in_check(), "{}", fen_writer::write_fen(game));
+            if depth > 1 {
+                walk(game, depth - 1);
+            }
+            unmake::unmake_move(game, movement);
+        }
+    }
+
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    ] {
+        walk(&mut fen_reader::read_fen(fen), 3);
+    }
+
+    let game = fen_reader::read_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+    assert!(game.in_check() && game.is_checkmate() && !game.is_stalemate());
+    assert!(game.legal_moves().is_empty());
+
+    let game = fen_reader::read_fen("k7/8/1Q6/8/8/8/8/7K b - - 0 1");
+    assert!(!game.in_check() && !game.is_checkmate() && game.is_stalemate());
+
+    // Castling checks with the rook.
+    let mut game = fen_reader::read_fen("5k2/8/8/8/8/8/8/4K2R w K - 0 1");
+    let castle = get_move("O-O", &mut game);
+    assert!(game.gives_check(&castle));
+    assert_eq!(get_san(&castle, &mut game), "O-O+");
+}
+
+#[test]
+fn engine_outlives_positions() {
+    use chess::alpha_beta_search::iterative_deepening_time_limit;
+    use std::time::Duration;
+
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    let mut engine = Engine::with_hash(16);
+
+    // What a search stores is still there when the same position is parsed again.
+    let mut game = fen_reader::read_fen(fen);
+    let result = iterative_deepening_time_limit(&mut game, &mut engine, 4, Duration::from_secs(600));
+    let entry = engine.transposition_table.probe(fen_reader::read_fen(fen).hash).unwrap();
+    assert!(entry.is_best_move(&result.best_move.unwrap()));
+
+    engine.new_game();
+    assert!(engine.transposition_table.probe(game.hash).is_none());
+}
+
+#[test]
+fn repeated_searches() {
+    use chess::alpha_beta_search::iterative_deepening_time_limit;
+    use chess::uci::move_to_uci;
+    use std::time::Duration;
+
+    // The second search finds the entries of the first one, the root is still searched.
+    let mut engine = Engine::with_hash(16);
+    for (fen, depth) in [
+        ("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", 4),
+        ("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", 5),
+    ] {
+        let first = iterative_deepening_time_limit(&mut fen_reader::read_fen(fen), &mut engine, depth, Duration::from_secs(600));
+        let second = iterative_deepening_time_limit(&mut fen_reader::read_fen(fen), &mut engine, depth, Duration::from_secs(600));
+
+        for result in [&first, &second] {
+            assert!(!result.pv.is_empty());
+            assert_eq!(result.pv.first(), result.best_move.as_ref());
+        }
+        assert_eq!(second.depth, first.depth);
+        if first.score.abs() > 0.9 {
+            assert_eq!(second.pv.iter().copied().map(move_to_uci).collect::<Vec<_>>(), ["a1a8"]);
+        }
+    }
+}
+
+#[test]
+fn make_move_requests() {
+    let fen = "4k3/1P6/8/8/8/8/3r4/R3K2R w KQ - 0 1";
+    let query = |build: fn(&mut MoveFen)| {
+        let mut query = MoveFen { fen: fen.to_string(), ..Default::default() };
+        build(&mut query);
+        requested_move(&query, &mut fen_reader::read_fen(fen))
+    };
+
+    // b7b8q given three ways.
+    let uci = query(|q| q.uci = Some("b7b8q".to_string())).unwrap();
+    let san = query(|q| q.san = Some("b8=Q+".to_string())).unwrap();
+    let indices = query(|q| {
+        q.origin = Some(49);
+        q.destiny = Some(57);
+        q.promotion = "q".to_string();
+    })
+    .unwrap();
+    assert_eq!((uci.origin, uci.destiny, uci.promotion), (82, 92, Some(piece::PieceType::Queen)));
+    assert_eq!(uci, san);
+    assert_eq!(uci, indices);
+
+    assert_eq!(query(|_| ()).unwrap_err(), MoveError::MissingMove);
+    assert_eq!(query(|q| q.uci = Some("b7b9".to_string())).unwrap_err(), MoveError::InvalidUci("b7b9".to_string()));
+    assert_eq!(query(|q| { q.origin = Some(64); q.destiny = Some(0); }).unwrap_err(), MoveError::InvalidSquare(64));
+    assert_eq!(query(|q| q.uci = Some("c3c4".to_string())).unwrap_err(), MoveError::EmptySquare("c3c4".to_string()));
+    assert_eq!(query(|q| q.uci = Some("d2d1".to_string())).unwrap_err(), MoveError::OpponentPiece("d2d1".to_string()));
+    assert_eq!(query(|q| q.uci = Some("e1c1".to_string())).unwrap_err(), MoveError::Unreachable("e1c1".to_string()));
+    assert_eq!(query(|q| q.uci = Some("e1d1".to_string())).unwrap_err(), MoveError::KingInCheck("e1d1".to_string()));
+    assert_eq!(query(|q| q.uci = Some("b7b8".to_string())).unwrap_err(), MoveError::MissingPromotion("b7b8".to_string()));
+    assert_eq!(query(|q| q.uci = Some("a1a2q".to_string())).unwrap_err(), MoveError::UnexpectedPromotion("a1a2".to_string()));
+    assert_eq!(query(|q| q.san = Some("Nf3".to_string())).unwrap_err(), MoveError::NoSanMatch("Nf3".to_string()));
+    assert_eq!(query(|q| q.san = Some("Kd1".to_string())).unwrap_err(), MoveError::NoSanMatch("Kd1".to_string()));
+}
+
+#[test]
+fn sessions_keep_the_history() {
+    let mut session = Session::new(None).unwrap();
+    for san in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"] {
+        let movement = notation::find_move(san, &mut session.position).unwrap();
+        session.play(movement);
+        assert_eq!(session.pgn.result, "*");
+    }
+    let movement = notation::find_move("Ng8", &mut session.position).unwrap();
+    session.play(movement);
+    assert_eq!(session.pgn.result, "1/2-1/2");
+    assert!(session.is_over());
+
+    session.undo();
+    assert_eq!(session.pgn.result, "*");
+    assert_eq!(session.san_moves(), ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"]);
+    while session.undo().is_some() {}
+    assert_eq!(session.fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+    let fen = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1";
+    let mut session = Session::new(Some(fen)).unwrap();
+    let movement = notation::find_move("Ra8#", &mut session.position).unwrap();
+    session.play(movement);
+    assert_eq!(session.pgn.result, "1-0");
+    assert!(session.to_pgn().contains("[FEN \"6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1\"]"));
+    assert!(session.to_pgn().contains("1. Ra8# 1-0"));
+}
+
+/// Server with the routes of `bin/main.rs` searching with `engine`.
+fn test_server(engine: Engine) -> tide::Server<api::State> {
+    let mut app = tide::with_state(api::State::new(engine));
+    app.at("/getBest").get(api::get_best);
+    app.at("/analyze").get(api::analyze);
+    app.at("/analysis").post(api::new_analysis);
+    app.at("/analysis/:id/events").get(api::analysis_events);
+    app.at("/analysis/:id/position").post(api::analysis_position);
+    app.at("/analysis/:id/stop").post(api::stop_analysis);
+    app.at("/games").post(api::new_game);
+    app.at("/games/:id").get(api::get_game);
+    app.at("/games/:id/moves").post(api::play_move);
+    app.at("/games/:id/engine-move").post(api::engine_move);
+    app.at("/games/:id/undo").post(api::undo_move);
+    app
+}
+
+fn send(app: &tide::Server<api::State>, method: http_types::Method, path: &str, body: &str) -> http_types::Response {
+    let mut request = http_types::Request::new(method, format!("http://localhost{}", path).as_str());
+    request.set_body(body);
+    async_std::task::block_on(app.respond(request)).unwrap()
+}
+
+/// Status and JSON body of the response.
+fn call_json(app: &tide::Server<api::State>, method: http_types::Method, path: &str, body: &str) -> (u16, serde_json::Value) {
+    let mut response = send(app, method, path, body);
+    let json: serde_json::Value = async_std::task::block_on(response.body_json()).unwrap();
+    (response.status() as u16, json)
+}
+
+#[test]
+fn games_endpoints() {
+    use chess::alpha_beta_search::SearchControl;
+    use http_types::Method::{Get, Post};
+    use std::sync::Arc;
+
+    let app = test_server(Engine::with_hash(16));
+    let call = |method, path: &str, body| call_json(&app, method, path, body);
+
+    let (status, game) = call(Post, "/games", "");
+    assert_eq!(status, 201);
+    let id = game["id"].as_u64().unwrap();
+
+    let (status, game) = call(Post, &format!("/games/{}/moves", id), r#"{"san": "e4"}"#);
+    assert_eq!(status, 200);
+    assert_eq!(game["moves"], serde_json::json!(["e4"]));
+
+    let (status, error) = call(Post, &format!("/games/{}/moves", id), r#"{"uci": "e2e4"}"#);
+    assert_eq!((status, error["error"].as_str()), (422, Some("empty_square")));
+
+    let (status, game) = call(Post, &format!("/games/{}/engine-move", id), r#"{"movetime": 100}"#);
+    assert_eq!(status, 200);
+    assert_eq!(game["moves"].as_array().unwrap().len(), 2);
+
+    let (status, game) = call(Post, &format!("/games/{}/undo", id), "");
+    assert_eq!(status, 200);
+    assert_eq!(game["fen"], "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+    assert!(game["pgn"].as_str().unwrap().contains("1. e4 *"));
+
+    // Taking e4 back for d4 while the engine thinks makes its move stale, though as many moves
+    // were played. A pondering engine thinks until it is stopped.
+    let mut engine = Engine::with_hash(16);
+    let control = Arc::new(SearchControl::pondering());
+    engine.control = control.clone();
+    let statistics = engine.statistics.clone();
+    let pondering_app = test_server(engine);
+    let id = call_json(&pondering_app, Post, "/games", "").1["id"].as_u64().unwrap();
+    assert_eq!(call_json(&pondering_app, Post, &format!("/games/{}/moves", id), r#"{"san": "e4"}"#).0, 200);
+
+    let thinking_app = pondering_app.clone();
+    let thinking = std::thread::spawn(move || call_json(&thinking_app, Post, &format!("/games/{}/engine-move", id), ""));
+    // The search copies the position before it counts its first node.
+    while statistics.nodes() == 0 {
+        std::thread::yield_now();
+    }
+    assert_eq!(call_json(&pondering_app, Post, &format!("/games/{}/undo", id), "").0, 200);
+    assert_eq!(call_json(&pondering_app, Post, &format!("/games/{}/moves", id), r#"{"san": "d4"}"#).0, 200);
+    control.stop();
+    let (status, error) = thinking.join().unwrap();
+    assert_eq!((status, error["error"].as_str()), (409, Some("game_changed")));
+
+    let (status, error) = call(Get, "/games/99", "");
+    assert_eq!((status, error["error"].as_str()), (404, Some("unknown_game")));
+    let (status, error) = call(Post, "/games", r#"{"fen": "8/8/8 w - - 0 1"}"#);
+    assert_eq!((status, error["error"].as_str()), (400, Some("invalid_fen")));
+}
+
+#[test]
+fn multi_pv() {
+    use chess::alpha_beta_search::{iterative_deepening_time_limit, SearchOptions};
+    use chess::uci::{move_to_uci, side_to_move_score, Score};
+    use std::time::Duration;
+
+    let mut engine = Engine::with_hash(16);
+    engine.options = SearchOptions { multi_pv: 3, ..SearchOptions::new() };
+
+    for (fen, mate) in [
+        ("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "a1a8"),
+        ("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", "a8a1"),
+    ] {
+        let mut game = fen_reader::read_fen(fen);
+        let result = iterative_deepening_time_limit(&mut game, &mut engine, 4, Duration::from_secs(600));
+
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!((result.lines[0].score, &result.lines[0].pv), (result.score, &result.pv));
+        assert_eq!(move_to_uci(result.best_move.unwrap()), mate);
+        assert_eq!(side_to_move_score(result.score, &game.turn), Score::Mate(1));
+
+        let scores: Vec<Score> = result.lines.iter().map(|line| side_to_move_score(line.score, &game.turn)).collect();
+        assert!(matches!(scores[1], Score::Cp(_)));
+        assert!(scores.windows(2).all(|pair| match pair {
+            [Score::Cp(a), Score::Cp(b)] => a >= b,
+            _ => true,
+        }));
+        let mut first_moves: Vec<String> = result.lines.iter().map(|line| move_to_uci(line.pv[0])).collect();
+        first_moves.dedup();
+        assert_eq!(first_moves.len(), 3);
+    }
+
+    // Never more lines than legal moves.
+    engine.options.multi_pv = 10;
+    let mut game = fen_reader::read_fen("7k/8/8/8/8/8/8/K7 w - - 0 1");
+    let result = iterative_deepening_time_limit(&mut game, &mut engine, 3, Duration::from_secs(600));
+    assert_eq!(result.lines.len(), 3);
+}
+
+#[test]
+fn best_move_endpoint() {
+    let app = test_server(Engine::with_hash(16));
+    let call = |query: &str| call_json(&app, http_types::Method::Get, &format!("/getBest?{}", query), "");
+
+    let (status, info) = call("fen=6k1/5ppp/8/8/8/8/5PPP/R5K1%20w%20-%20-%200%201&depth=100");
+    assert_eq!(status, 200);
+    assert_eq!(info["pv"][0], "a1a8");
+    assert_eq!(info["fen"], "R5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 1 1");
+
+    let (status, error) = call("fen=6k1/5ppp/8/8/8/8/5PPP/R5K1%20w%20-%20-%200%201&depth=-1");
+    assert_eq!((status, error["error"].as_str()), (400, Some("invalid_depth")));
+    let (status, error) = call("fen=R5k1/5ppp/8/8/8/8/5PPP/6K1%20b%20-%20-%201%201&depth=100");
+    assert_eq!((status, error["error"].as_str()), (422, Some("no_legal_moves")));
+}
+
+#[test]
+fn analyze_endpoint() {
+    let app = test_server(Engine::with_hash(16));
+    let call = |query: &str| call_json(&app, http_types::Method::Get, &format!("/analyze?{}", query), "");
+
+    let (status, analysis) = call("fen=6k1/5ppp/8/8/8/8/5PPP/R5K1%20w%20-%20-%200%201&depth=3&multipv=2");
+    assert_eq!(status, 200);
+    assert_eq!(analysis["bestmove"], "a1a8");
+    assert_eq!(analysis["bestmove_san"], "Ra8#");
+    assert_eq!(analysis["lines"][0]["score"], serde_json::json!({ "mate": 1 }));
+    assert_eq!(analysis["lines"][0]["pv_san"], serde_json::json!(["Ra8#"]));
+    assert_eq!(analysis["lines"][1]["multipv"], 2);
+    assert!(analysis["lines"][1]["score"]["cp"].is_i64());
+
+    let (status, error) = call("fen=6k1/5ppp/8/8/8/8/5PPP/R5K1%20w%20-%20-%200%201&multipv=0");
+    assert_eq!((status, error["error"].as_str()), (400, Some("invalid_multipv")));
+    let (status, error) = call("fen=8/8&movetime=10");
+    assert_eq!((status, error["error"].as_str()), (400, Some("invalid_fen")));
+}
+
+#[test]
+fn live_analysis() {
+    use chess::alpha_beta_search::SearchStatistics;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let app = test_server(Engine::with_hash(16));
+    // The events of a whole stream, as (name, data) pairs.
+    let events = |mut response: http_types::Response| {
+        let text = async_std::task::block_on(response.body_string()).unwrap();
+        text.split("\n\n")
+            .filter_map(|event| {
+                let field = |name: &str| event.lines().find_map(|line| line.strip_prefix(name)).map(|value| value.trim().to_string());
+                Some((field("event:")?, serde_json::from_str::<serde_json::Value>(&field("data:")?).unwrap()))
+            })
+            .collect::<Vec<_>>()
+    };
+    use http_types::Method::{Get, Post};
+    let new_analysis = |app: &tide::Server<api::State>, body: &str| {
+        let (status, analysis) = call_json(app, Post, "/analysis", body);
+        assert_eq!(status, 201);
+        analysis["id"].as_u64().unwrap()
+    };
+    // A server whose search is seen running through the counters of its engine.
+    let watched_server = || {
+        let engine = Engine::with_hash(16);
+        let statistics = engine.statistics.clone();
+        (test_server(engine), statistics)
+    };
+    // Reads the event stream of `id` on another thread, returns once its search runs.
+    let open_events = |app: &tide::Server<api::State>, statistics: &SearchStatistics, id: u64| {
+        let events_app = app.clone();
+        let reader = thread::spawn(move || events(send(&events_app, Get, &format!("/analysis/{}/events", id), "")));
+        while statistics.nodes() == 0 {
+            thread::yield_now();
+        }
+        reader
+    };
+
+    // One info event per depth, then the best move.
+    let id = new_analysis(&app, r#"{"fen": "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "depth": 3, "multipv": 2}"#);
+    let stream = events(send(&app, Get, &format!("/analysis/{}/events", id), ""));
+    let names: Vec<&str> = stream.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names.last(), Some(&"bestmove"));
+    assert!(names.iter().filter(|name| **name == "info").count() >= 1);
+    assert_eq!(stream[0].1["lines"][0]["pv_san"][0], "Ra8#");
+    assert_eq!(stream[0].1["lines"].as_array().unwrap().len(), 2);
+    // The stream is over and the analysis forgotten.
+    assert_eq!(send(&app, Get, &format!("/analysis/{}/events", id), "").status() as u16, 404);
+    assert_eq!(send(&app, Post, &format!("/analysis/{}/stop", id), "").status() as u16, 404);
+
+    // A new position replaces the one being searched.
+    let (app, statistics) = watched_server();
+    let id = new_analysis(&app, r#"{"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"}"#);
+    let reader = open_events(&app, &statistics, id);
+    let response = send(&app, Post, &format!("/analysis/{}/position", id), r#"{"fen": "r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", "depth": 2}"#);
+    assert_eq!(response.status() as u16, 202);
+    let stream = reader.join().unwrap();
+    let best_moves: Vec<&serde_json::Value> = stream.iter().filter(|(name, _)| name == "bestmove").map(|(_, data)| data).collect();
+    assert_eq!(best_moves.len(), 2);
+    assert_eq!(best_moves[1]["bestmove"], "a8a1");
+
+    // Stopping ends the stream long before the minute of a search without limits.
+    let (app, statistics) = watched_server();
+    let id = new_analysis(&app, r#"{"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"}"#);
+    let reader = open_events(&app, &statistics, id);
+    let start = Instant::now();
+    assert_eq!(send(&app, Post, &format!("/analysis/{}/stop", id), "").status() as u16, 202);
+    let stream = reader.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(stream.last().map(|(name, _)| name.as_str()), Some("bestmove"));
+}
+
+#[test]
+fn deepest_ply() {
+    use chess::alpha_beta_search::{alpha_beta_max, alpha_beta_min, quiescence_max, QUIESCENCE_CHECKS};
+    use std::time::{Duration, Instant};
+
+    // Past the end of the tables indexed by ply the search stops at the static evaluation.
+    let mut game = fen_reader::read_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    let mut engine = Engine::with_hash(16);
+    let (start, limit) = (Instant::now(), Duration::from_secs(600));
+    let ply = (game::MAX_PLY - 1) as i8;
+    let evaluation = eval::static_evaluate(&mut game);
+
+    assert_eq!(alpha_beta_max(-100.0, 100.0, 4, &mut game, &mut engine, &mut Vec::new(), &start, &limit, ply, 4), evaluation);
+    assert_eq!(alpha_beta_min(-100.0, 100.0, 4, &mut game, &mut engine, &mut Vec::new(), &start, &limit, ply, 4), evaluation);
+    assert_eq!(quiescence_max(-100.0, 100.0, &mut game, &mut engine, &start, &limit, QUIESCENCE_CHECKS, ply, &eval::static_evaluate), evaluation);
+    // A search started a few plies before the end does not run past it.
+    let score = alpha_beta_max(-100.0, 100.0, 4, &mut game, &mut engine, &mut Vec::new(), &start, &limit, ply - 3, 4);
+    assert!(score.abs() < 1.0);
+}